@@ -0,0 +1,17 @@
+-- Per-row validation errors recorded while processing an upload
+
+ALTER TABLE uploads ADD COLUMN IF NOT EXISTS source_headers TEXT;
+
+CREATE TABLE IF NOT EXISTS upload_row_errors (
+    id SERIAL PRIMARY KEY,
+    upload_id INTEGER NOT NULL REFERENCES uploads(id) ON DELETE CASCADE,
+    row_number INTEGER NOT NULL,
+    column_name VARCHAR(100),
+    raw_value TEXT,
+    reason TEXT NOT NULL,
+    rejected BOOLEAN NOT NULL DEFAULT FALSE,
+    raw_row TEXT,
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_upload_row_errors_upload ON upload_row_errors(upload_id, row_number);
@@ -0,0 +1,9 @@
+-- Fields decoded from the VIN at ingest time
+
+ALTER TABLE vehicles ADD COLUMN IF NOT EXISTS wmi VARCHAR(3);
+ALTER TABLE vehicles ADD COLUMN IF NOT EXISTS manufacturer VARCHAR(100);
+ALTER TABLE vehicles ADD COLUMN IF NOT EXISTS model_year INTEGER;
+ALTER TABLE vehicles ADD COLUMN IF NOT EXISTS plant_code VARCHAR(1);
+
+CREATE INDEX IF NOT EXISTS idx_vehicles_dealer_manufacturer ON vehicles(dealer_id, manufacturer);
+CREATE INDEX IF NOT EXISTS idx_vehicles_dealer_model_year ON vehicles(dealer_id, model_year);
@@ -0,0 +1,3 @@
+-- Uploads can opt into being imported in a single transaction
+
+ALTER TABLE uploads ADD COLUMN IF NOT EXISTS all_or_nothing BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,37 @@
+-- Track vehicles by (dealer_id, vin) across uploads instead of duplicating them
+
+CREATE TABLE IF NOT EXISTS vehicle_uploads (
+    vehicle_id INTEGER NOT NULL REFERENCES vehicles(id) ON DELETE CASCADE,
+    upload_id INTEGER NOT NULL REFERENCES uploads(id) ON DELETE CASCADE,
+    change_type VARCHAR(20) NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    PRIMARY KEY (vehicle_id, upload_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_vehicle_uploads_upload ON vehicle_uploads(upload_id);
+
+-- Link every existing vehicle row to the newest row for its VIN, which is the one kept below
+INSERT INTO vehicle_uploads (vehicle_id, upload_id, change_type)
+SELECT k.keep_id, v.upload_id, CASE WHEN v.id = k.first_id THEN 'new' ELSE 'updated' END
+FROM vehicles v
+JOIN (
+    SELECT dealer_id, vin, MIN(id) AS first_id, MAX(id) AS keep_id
+    FROM vehicles
+    GROUP BY dealer_id, vin
+) k ON k.dealer_id = v.dealer_id AND k.vin = v.vin
+ON CONFLICT (vehicle_id, upload_id) DO NOTHING;
+
+DELETE FROM vehicles v
+USING vehicles w
+WHERE v.dealer_id = w.dealer_id AND v.vin = w.vin AND v.id < w.id;
+
+DELETE FROM scored_leads a
+USING scored_leads b
+WHERE a.vehicle_id = b.vehicle_id AND a.id < b.id;
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_vehicles_dealer_vin ON vehicles(dealer_id, vin);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_scored_leads_vehicle_unique ON scored_leads(vehicle_id);
+
+ALTER TABLE uploads ADD COLUMN IF NOT EXISTS new_count INTEGER DEFAULT 0;
+ALTER TABLE uploads ADD COLUMN IF NOT EXISTS updated_count INTEGER DEFAULT 0;
+ALTER TABLE uploads ADD COLUMN IF NOT EXISTS unchanged_count INTEGER DEFAULT 0;
@@ -0,0 +1,3 @@
+-- Worksheet to read from Excel uploads (NULL means the first sheet)
+
+ALTER TABLE uploads ADD COLUMN IF NOT EXISTS sheet_name VARCHAR(255);
@@ -0,0 +1,6 @@
+-- Progress fields streamed by GET /api/uploads/:id/progress
+
+ALTER TABLE uploads ADD COLUMN IF NOT EXISTS phase VARCHAR(30) DEFAULT 'queued';
+ALTER TABLE uploads ADD COLUMN IF NOT EXISTS rows_failed INTEGER DEFAULT 0;
+
+UPDATE uploads SET phase = status WHERE phase = 'queued' AND status IN ('completed', 'error');
@@ -0,0 +1,3 @@
+-- How a dealer's files write all-numeric dates: 'mdy' (03/15/2025) or 'dmy' (15/03/2025)
+
+ALTER TABLE dealers ADD COLUMN IF NOT EXISTS date_order VARCHAR(3) NOT NULL DEFAULT 'mdy';
@@ -0,0 +1,11 @@
+-- Phone numbers are stored in E.164 form using the dealer's default country, with
+-- the extension kept separately. contact_status is 'valid', 'invalid' or 'missing';
+-- it is NULL for vehicles imported before phones were validated.
+
+ALTER TABLE dealers ADD COLUMN IF NOT EXISTS default_country VARCHAR(2) NOT NULL DEFAULT 'US';
+
+ALTER TABLE vehicles ALTER COLUMN customer_phone TYPE VARCHAR(50);
+ALTER TABLE vehicles ADD COLUMN IF NOT EXISTS customer_phone_ext VARCHAR(20);
+ALTER TABLE vehicles ADD COLUMN IF NOT EXISTS contact_status VARCHAR(10);
+
+CREATE INDEX IF NOT EXISTS idx_vehicles_contact_status ON vehicles(dealer_id, contact_status);
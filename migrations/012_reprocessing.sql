@@ -0,0 +1,9 @@
+-- Re-running an upload: jobs carry a mode, and leads and uploads record the
+-- scoring version that produced them (NULL for leads scored before versioning).
+
+ALTER TABLE upload_jobs ADD COLUMN IF NOT EXISTS mode VARCHAR(20) NOT NULL DEFAULT 'import';
+
+ALTER TABLE scored_leads ADD COLUMN IF NOT EXISTS scoring_version VARCHAR(20);
+
+ALTER TABLE uploads ADD COLUMN IF NOT EXISTS scoring_version VARCHAR(20);
+ALTER TABLE uploads ADD COLUMN IF NOT EXISTS reprocessed_at TIMESTAMPTZ;
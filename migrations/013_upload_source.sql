@@ -0,0 +1,3 @@
+-- Where an upload came from: 'file' for multipart uploads, 'api' for POST /api/vehicles/batch
+
+ALTER TABLE uploads ADD COLUMN IF NOT EXISTS source VARCHAR(20) NOT NULL DEFAULT 'file';
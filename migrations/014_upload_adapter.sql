@@ -0,0 +1,3 @@
+-- Import adapter that reads an upload's rows (NULL until detected from the header row)
+
+ALTER TABLE uploads ADD COLUMN IF NOT EXISTS adapter VARCHAR(50);
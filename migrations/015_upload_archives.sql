@@ -0,0 +1,6 @@
+-- Files extracted from a .zip, .gz or .tar.gz upload are child uploads of the archive's upload
+
+ALTER TABLE uploads ADD COLUMN IF NOT EXISTS parent_id INTEGER REFERENCES uploads(id);
+ALTER TABLE uploads ADD COLUMN IF NOT EXISTS child_count INTEGER NOT NULL DEFAULT 0;
+
+CREATE INDEX IF NOT EXISTS idx_uploads_parent_id ON uploads(parent_id);
@@ -0,0 +1,4 @@
+-- Detected text encoding and delimiter of CSV uploads (NULL for Excel files)
+
+ALTER TABLE uploads ADD COLUMN IF NOT EXISTS encoding VARCHAR(20);
+ALTER TABLE uploads ADD COLUMN IF NOT EXISTS delimiter VARCHAR(20);
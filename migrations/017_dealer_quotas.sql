@@ -0,0 +1,8 @@
+-- Per-dealer limits. NULL uses the server default (QUOTA_* environment variables)
+
+ALTER TABLE dealers ADD COLUMN IF NOT EXISTS max_rows_per_upload INTEGER;
+ALTER TABLE dealers ADD COLUMN IF NOT EXISTS max_uploads_per_day INTEGER;
+ALTER TABLE dealers ADD COLUMN IF NOT EXISTS max_stored_vehicles INTEGER;
+ALTER TABLE dealers ADD COLUMN IF NOT EXISTS max_concurrent_jobs INTEGER;
+
+CREATE INDEX IF NOT EXISTS idx_uploads_dealer_uploaded_at ON uploads(dealer_id, uploaded_at);
@@ -0,0 +1,9 @@
+-- Each dealer's scoring strategy and its configuration (weights, day thresholds and
+-- call windows). Dealers without a row are scored with the defaults.
+
+CREATE TABLE IF NOT EXISTS dealer_scoring_settings (
+    dealer_id INTEGER PRIMARY KEY REFERENCES dealers(id) ON DELETE CASCADE,
+    strategy VARCHAR(50) NOT NULL DEFAULT 'weighted',
+    config JSONB NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
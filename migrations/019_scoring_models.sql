@@ -0,0 +1,17 @@
+-- Registry of scoring models: each distinct strategy and config scored under one
+-- version of the scoring rules. Leads record the model that scored them and a
+-- snapshot of the inputs it read (NULL for leads scored before the registry).
+
+CREATE TABLE IF NOT EXISTS scoring_models (
+    id SERIAL PRIMARY KEY,
+    scoring_version VARCHAR(20) NOT NULL,
+    strategy VARCHAR(50) NOT NULL,
+    config JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (scoring_version, strategy, config)
+);
+
+ALTER TABLE scored_leads ADD COLUMN IF NOT EXISTS model_id INTEGER REFERENCES scoring_models(id);
+ALTER TABLE scored_leads ADD COLUMN IF NOT EXISTS inputs JSONB;
+
+CREATE INDEX IF NOT EXISTS idx_scored_leads_model ON scored_leads(model_id);
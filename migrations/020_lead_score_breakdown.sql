@@ -0,0 +1,4 @@
+-- Per-factor explanation of each lead's urgency score: the raw input, bucket hit,
+-- weight and contribution of every factor (NULL for leads scored before it existed)
+
+ALTER TABLE scored_leads ADD COLUMN IF NOT EXISTS breakdown JSONB;
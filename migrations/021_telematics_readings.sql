@@ -0,0 +1,15 @@
+-- Connected-vehicle readings, keyed by VIN within a dealer. A VIN's latest recent
+-- reading feeds the telematic score of its lead.
+
+CREATE TABLE IF NOT EXISTS telematics_readings (
+    id SERIAL PRIMARY KEY,
+    dealer_id INTEGER NOT NULL REFERENCES dealers(id) ON DELETE CASCADE,
+    vin VARCHAR(17) NOT NULL,
+    recorded_at TIMESTAMPTZ NOT NULL,
+    odometer_miles INTEGER,
+    dtc_codes TEXT[] NOT NULL DEFAULT '{}',
+    battery_voltage REAL,
+    oil_life_percent REAL,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    UNIQUE (dealer_id, vin, recorded_at)
+);
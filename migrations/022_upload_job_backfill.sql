@@ -0,0 +1,23 @@
+-- Archive uploads are 'extracting' only while their files are extracted. Ones an
+-- earlier version left 'extracting' while their files were pending are 'processing'.
+
+UPDATE uploads SET status = 'processing' WHERE status = 'extracting' AND child_count > 0;
+
+-- Uploads left in 'processing' by the old in-process spawner get a job so they are
+-- retried. This runs once, recorded in migration_markers, and skips archive uploads
+-- and API batches.
+
+CREATE TABLE IF NOT EXISTS migration_markers (
+    name VARCHAR(100) PRIMARY KEY,
+    applied_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+WITH marker AS (
+    INSERT INTO migration_markers (name) VALUES ('upload_job_backfill')
+    ON CONFLICT (name) DO NOTHING
+    RETURNING name
+)
+INSERT INTO upload_jobs (upload_id)
+SELECT u.id FROM uploads u, marker
+WHERE u.status = 'processing' AND u.child_count = 0 AND u.source <> 'api'
+ON CONFLICT (upload_id) DO NOTHING;
@@ -0,0 +1,6 @@
+-- The claimed file a watched-directory upload was made from, so a watcher stopped
+-- between creating the upload and filing the file can tell whether it was imported
+
+ALTER TABLE uploads ADD COLUMN IF NOT EXISTS watch_path TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_uploads_watch_path ON uploads(dealer_id, watch_path) WHERE watch_path IS NOT NULL;
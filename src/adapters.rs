@@ -0,0 +1,477 @@
+use crate::ingest::{normalize_header, ColumnMap};
+use crate::models::VehicleInput;
+use chrono::NaiveDate;
+use csv::StringRecord;
+
+/// Id of the adapter that reads files using our own column names and their aliases.
+pub const GENERIC_ADAPTER: &str = "generic";
+
+/// Turns the rows of one export layout into `VehicleInput`s. Each upload is read by
+/// exactly one adapter, chosen by the dealer or detected from the file's header row.
+pub trait ImportAdapter: Send + Sync {
+    /// Stable id stored in `uploads.adapter` and accepted by the `adapter` form field
+    fn id(&self) -> &'static str;
+
+    /// Human-readable name, for listings
+    fn name(&self) -> &'static str;
+
+    /// Whether a header row looks like this adapter's layout.
+    fn detect(&self, headers: &StringRecord) -> bool;
+
+    /// Resolves the layout's columns within `headers`, failing if a required one is missing.
+    fn bind(&self, headers: &StringRecord) -> anyhow::Result<Box<dyn RowMapper>>;
+}
+
+/// An adapter bound to one file's header row.
+pub trait RowMapper: Send {
+    /// The header(s) read for each canonical column, in canonical column order.
+    fn mapping(&self) -> Vec<(&'static str, Option<String>)>;
+
+    /// Reads one data row, leaving all validation to `validate_input`.
+    fn to_input(&self, record: &StringRecord) -> VehicleInput;
+}
+
+/// Every adapter, with the DMS layouts ahead of the generic fallback.
+pub fn adapters() -> Vec<&'static dyn ImportAdapter> {
+    let mut all: Vec<&'static dyn ImportAdapter> = DMS_LAYOUTS.iter().map(|l| l as &dyn ImportAdapter).collect();
+    all.push(&GenericCsvAdapter);
+    all
+}
+
+pub fn adapter_by_id(id: &str) -> Option<&'static dyn ImportAdapter> {
+    let id = id.trim().to_ascii_lowercase();
+    adapters().into_iter().find(|adapter| adapter.id() == id)
+}
+
+/// The first DMS layout whose fingerprint matches `headers`, else the generic adapter.
+pub fn detect_adapter(headers: &StringRecord) -> &'static dyn ImportAdapter {
+    adapters()
+        .into_iter()
+        .find(|adapter| adapter.detect(headers))
+        .unwrap_or(&GenericCsvAdapter)
+}
+
+/// Reads files whose headers are our column names or one of their aliases.
+pub struct GenericCsvAdapter;
+
+impl ImportAdapter for GenericCsvAdapter {
+    fn id(&self) -> &'static str {
+        GENERIC_ADAPTER
+    }
+
+    fn name(&self) -> &'static str {
+        "Generic CSV/Excel"
+    }
+
+    fn detect(&self, _headers: &StringRecord) -> bool {
+        true
+    }
+
+    fn bind(&self, headers: &StringRecord) -> anyhow::Result<Box<dyn RowMapper>> {
+        Ok(Box::new(GenericMapper {
+            columns: ColumnMap::from_headers(headers)?,
+            headers: headers.clone(),
+        }))
+    }
+}
+
+struct GenericMapper {
+    columns: ColumnMap,
+    headers: StringRecord,
+}
+
+impl RowMapper for GenericMapper {
+    fn mapping(&self) -> Vec<(&'static str, Option<String>)> {
+        self.columns.mapping(&self.headers)
+    }
+
+    fn to_input(&self, record: &StringRecord) -> VehicleInput {
+        self.columns.to_input(record)
+    }
+}
+
+// Where a DMS layout reads one canonical column from. Header names are given
+// normalized (lowercased, non-alphanumerics stripped), as in `ingest`.
+enum Source {
+    /// A single column
+    Column(&'static str),
+    /// The first non-blank of several columns, e.g. cell phone then home phone
+    FirstOf(&'static [&'static str]),
+    /// Several columns joined with a space, e.g. first and last name
+    Joined(&'static [&'static str]),
+    /// A `LAST, FIRST` name column, read as `FIRST LAST`
+    LastCommaFirst(&'static str),
+}
+
+impl Source {
+    fn headers(&self) -> &[&'static str] {
+        match self {
+            Source::Column(header) | Source::LastCommaFirst(header) => std::slice::from_ref(header),
+            Source::FirstOf(headers) | Source::Joined(headers) => headers,
+        }
+    }
+}
+
+// How a DMS layout rewrites a value it has read, before validation.
+#[derive(Clone, Copy)]
+enum Transform {
+    /// Kept as read
+    None,
+    /// A date in one of the layout's `date_formats`, rewritten as `YYYY-MM-DD` so the
+    /// dealer's date order isn't consulted; other values are left to the usual parsing
+    Date,
+    /// A US ZIP code that lost its leading zeros or its dash to a spreadsheet,
+    /// e.g. `2134` or `21341234`, restored to `02134` or `02134-1234`
+    ZipCode,
+}
+
+struct Field {
+    column: &'static str,
+    source: Source,
+    required: bool,
+    transform: Transform,
+}
+
+impl Field {
+    const fn with(self, transform: Transform) -> Field {
+        Field { transform, ..self }
+    }
+}
+
+const fn required(column: &'static str, source: Source) -> Field {
+    Field {
+        column,
+        source,
+        required: true,
+        transform: Transform::None,
+    }
+}
+
+const fn optional(column: &'static str, source: Source) -> Field {
+    Field {
+        column,
+        source,
+        required: false,
+        transform: Transform::None,
+    }
+}
+
+/// A fixed DMS export layout. It is detected when every `fingerprint` header is
+/// present, and each canonical column is read from the layout's own headers.
+pub struct DmsLayout {
+    id: &'static str,
+    name: &'static str,
+    fingerprint: &'static [&'static str],
+    // chrono formats the export writes dates in, tried in order
+    date_formats: &'static [&'static str],
+    // In canonical column order: vin, warranty_exp_date, customer_name,
+    // customer_phone, customer_email, customer_zip, last_service_date
+    fields: [Field; 7],
+}
+
+static DMS_LAYOUTS: [DmsLayout; 3] = [
+    DmsLayout {
+        id: "cdk",
+        name: "CDK Global service customer export",
+        fingerprint: &["customerfirstname", "customerlastname", "lastrodate"],
+        date_formats: &["%m/%d/%Y", "%m/%d/%Y %H:%M:%S"],
+        fields: [
+            required("vin", Source::Column("vin")),
+            optional("warranty_exp_date", Source::Column("warrantyexpirationdate")).with(Transform::Date),
+            required("customer_name", Source::Joined(&["customerfirstname", "customerlastname"])),
+            required("customer_phone", Source::FirstOf(&["cellphone", "homephone", "businessphone"])),
+            optional("customer_email", Source::Column("emailaddress")),
+            optional("customer_zip", Source::Column("zipcode")).with(Transform::ZipCode),
+            optional("last_service_date", Source::Column("lastrodate")).with(Transform::Date),
+        ],
+    },
+    DmsLayout {
+        id: "reynolds",
+        name: "Reynolds & Reynolds ERA customer vehicle export",
+        fingerprint: &["vehicleidnumber", "custnamelastfirst"],
+        date_formats: &["%m/%d/%y", "%m/%d/%Y"],
+        fields: [
+            required("vin", Source::Column("vehicleidnumber")),
+            optional("warranty_exp_date", Source::Column("warrexpdate")).with(Transform::Date),
+            required("customer_name", Source::LastCommaFirst("custnamelastfirst")),
+            required("customer_phone", Source::FirstOf(&["cellphone", "homephone", "workphone"])),
+            optional("customer_email", Source::Column("email")),
+            optional("customer_zip", Source::Column("zip")).with(Transform::ZipCode),
+            optional("last_service_date", Source::Column("lastsvcdate")).with(Transform::Date),
+        ],
+    },
+    DmsLayout {
+        id: "dealertrack",
+        name: "Dealertrack DMS customer export",
+        fingerprint: &["firstname", "lastname", "phone1", "factorywarrantyend"],
+        date_formats: &["%Y-%m-%d", "%Y-%m-%d %H:%M:%S", "%Y%m%d"],
+        fields: [
+            required("vin", Source::Column("vin")),
+            optional("warranty_exp_date", Source::Column("factorywarrantyend")).with(Transform::Date),
+            required("customer_name", Source::Joined(&["firstname", "lastname"])),
+            required("customer_phone", Source::FirstOf(&["phone1", "phone2"])),
+            optional("customer_email", Source::Column("email")),
+            optional("customer_zip", Source::Column("postalcode")).with(Transform::ZipCode),
+            optional("last_service_date", Source::Column("lastservicedate")).with(Transform::Date),
+        ],
+    },
+];
+
+impl ImportAdapter for DmsLayout {
+    fn id(&self) -> &'static str {
+        self.id
+    }
+
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn detect(&self, headers: &StringRecord) -> bool {
+        let normalized: Vec<String> = headers.iter().map(normalize_header).collect();
+        self.fingerprint.iter().all(|header| normalized.iter().any(|h| h == header))
+    }
+
+    fn bind(&self, headers: &StringRecord) -> anyhow::Result<Box<dyn RowMapper>> {
+        let normalized: Vec<String> = headers.iter().map(normalize_header).collect();
+        let mut fields = Vec::with_capacity(self.fields.len());
+        let mut missing = Vec::new();
+
+        for field in &self.fields {
+            // Multi-column sources only need one of their columns present
+            let indexes: Vec<usize> = field
+                .source
+                .headers()
+                .iter()
+                .filter_map(|header| normalized.iter().position(|h| h == header))
+                .collect();
+            if indexes.is_empty() && field.required {
+                missing.push(field.column);
+            }
+            fields.push(BoundField {
+                column: field.column,
+                joined: matches!(field.source, Source::Joined(_)),
+                last_comma_first: matches!(field.source, Source::LastCommaFirst(_)),
+                transform: field.transform,
+                indexes,
+            });
+        }
+
+        if !missing.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Missing required column(s) for the {} layout: {}. Found headers: {}",
+                self.name,
+                missing.join(", "),
+                headers.iter().collect::<Vec<_>>().join(", ")
+            ));
+        }
+
+        Ok(Box::new(LayoutMapper {
+            fields,
+            headers: headers.clone(),
+            date_formats: self.date_formats,
+        }))
+    }
+}
+
+struct BoundField {
+    column: &'static str,
+    indexes: Vec<usize>,
+    joined: bool,
+    last_comma_first: bool,
+    transform: Transform,
+}
+
+struct LayoutMapper {
+    fields: Vec<BoundField>,
+    headers: StringRecord,
+    date_formats: &'static [&'static str],
+}
+
+impl LayoutMapper {
+    // Trimmed and transformed value of the field at `position` in canonical order,
+    // `None` if blank
+    fn value(&self, record: &StringRecord, position: usize) -> Option<String> {
+        let field = &self.fields[position];
+        let mut values = field
+            .indexes
+            .iter()
+            .filter_map(|&i| record.get(i))
+            .map(str::trim)
+            .filter(|s| !s.is_empty());
+
+        let value = if field.joined {
+            values.collect::<Vec<_>>().join(" ")
+        } else if field.last_comma_first {
+            let name = values.next()?;
+            match name.split_once(',') {
+                Some((last, first)) => format!("{} {}", first.trim(), last.trim()).trim().to_string(),
+                None => name.to_string(),
+            }
+        } else {
+            values.next()?.to_string()
+        };
+        let value = match field.transform {
+            Transform::None => value,
+            Transform::Date => self.iso_date(&value).unwrap_or(value),
+            Transform::ZipCode => restore_zip(&value).unwrap_or(value),
+        };
+        Some(value).filter(|s| !s.is_empty())
+    }
+
+    fn iso_date(&self, value: &str) -> Option<String> {
+        self.date_formats
+            .iter()
+            .find_map(|format| NaiveDate::parse_from_str(value, format).ok())
+            .map(|date| date.format("%Y-%m-%d").to_string())
+    }
+}
+
+fn restore_zip(value: &str) -> Option<String> {
+    if !value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    match value.len() {
+        3..=5 => Some(format!("{:0>5}", value)),
+        7..=9 => {
+            let padded = format!("{:0>9}", value);
+            Some(format!("{}-{}", &padded[..5], &padded[5..]))
+        }
+        _ => None,
+    }
+}
+
+impl RowMapper for LayoutMapper {
+    fn mapping(&self) -> Vec<(&'static str, Option<String>)> {
+        self.fields
+            .iter()
+            .map(|field| {
+                let headers: Vec<&str> = field.indexes.iter().filter_map(|&i| self.headers.get(i)).collect();
+                let separator = if field.joined { " + " } else { " / " };
+                (field.column, Some(headers.join(separator)).filter(|s| !s.is_empty()))
+            })
+            .collect()
+    }
+
+    fn to_input(&self, record: &StringRecord) -> VehicleInput {
+        VehicleInput {
+            vin: self.value(record, 0).unwrap_or_default(),
+            warranty_exp_date: self.value(record, 1),
+            customer_name: self.value(record, 2).unwrap_or_default(),
+            customer_phone: self.value(record, 3).unwrap_or_default(),
+            customer_email: self.value(record, 4),
+            customer_zip: self.value(record, 5),
+            last_service_date: self.value(record, 6),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dates::DateOrder;
+    use crate::ingest::{validate_input, ImportSettings};
+    use phonenumber::country::Id as CountryId;
+
+    fn read(adapter: &str, headers: &[&str], row: &[&str]) -> VehicleInput {
+        let headers = StringRecord::from(headers.to_vec());
+        let layout = adapter_by_id(adapter).unwrap();
+        assert_eq!(detect_adapter(&headers).id(), adapter);
+        layout.bind(&headers).unwrap().to_input(&StringRecord::from(row.to_vec()))
+    }
+
+    #[test]
+    fn cdk_dates_are_month_first_and_zips_restored() {
+        let headers = [
+            "VIN", "Customer First Name", "Customer Last Name", "Cell Phone", "Home Phone", "Zip Code",
+            "Warranty Expiration Date", "Last RO Date",
+        ];
+        let input = read(
+            "cdk",
+            &headers,
+            &["1HGCM82633A004352", "Bob", "Jones", "", "202-555-0132", "2134", "03/04/2026", "11/02/2024 00:00:00"],
+        );
+        assert_eq!(input.customer_name, "Bob Jones");
+        assert_eq!(input.customer_phone, "202-555-0132");
+        assert_eq!(input.customer_zip.as_deref(), Some("02134"));
+        assert_eq!(input.warranty_exp_date.as_deref(), Some("2026-03-04"));
+        assert_eq!(input.last_service_date.as_deref(), Some("2024-11-02"));
+
+        // A dealer set to day-first still gets the export's month-first dates
+        let settings = ImportSettings {
+            date_order: DateOrder::DayFirst,
+            default_country: CountryId::US,
+        };
+        let outcome = validate_input(&input, &settings);
+        assert!(outcome.issues.is_empty(), "{:?}", outcome.issues);
+        assert_eq!(
+            outcome.vehicle.unwrap().warranty_exp_date,
+            NaiveDate::from_ymd_opt(2026, 3, 4)
+        );
+    }
+
+    #[test]
+    fn reynolds_reads_two_digit_years_and_last_comma_first_names() {
+        let headers = [
+            "VehicleIDNumber", "CustNameLastFirst", "CellPhone", "WorkPhone", "Zip", "WarrExpDate", "LastSvcDate",
+        ];
+        let input = read(
+            "reynolds",
+            &headers,
+            &["1HGCM82633A004352", "JONES, BOB", "202-555-0132", "", "021341234", "03/04/26", "11/02/2024"],
+        );
+        assert_eq!(input.customer_name, "BOB JONES");
+        assert_eq!(input.customer_zip.as_deref(), Some("02134-1234"));
+        assert_eq!(input.warranty_exp_date.as_deref(), Some("2026-03-04"));
+        assert_eq!(input.last_service_date.as_deref(), Some("2024-11-02"));
+    }
+
+    #[test]
+    fn dealertrack_reads_iso_and_compact_dates() {
+        let headers = [
+            "VIN", "FirstName", "LastName", "Phone1", "Phone2", "PostalCode", "FactoryWarrantyEnd", "LastServiceDate",
+        ];
+        let input = read(
+            "dealertrack",
+            &headers,
+            &["1HGCM82633A004352", "Bob", "Jones", "", "202-555-0132", "M5V 3L9", "20260304", "2024-11-02 08:30:00"],
+        );
+        assert_eq!(input.customer_phone, "202-555-0132");
+        assert_eq!(input.customer_zip.as_deref(), Some("M5V 3L9"));
+        assert_eq!(input.warranty_exp_date.as_deref(), Some("2026-03-04"));
+        assert_eq!(input.last_service_date.as_deref(), Some("2024-11-02"));
+    }
+
+    #[test]
+    fn values_outside_the_layout_format_are_left_as_read() {
+        let headers = [
+            "VIN", "Customer First Name", "Customer Last Name", "Cell Phone", "Zip Code", "Warranty Expiration Date",
+            "Last RO Date",
+        ];
+        let input = read(
+            "cdk",
+            &headers,
+            &["1HGCM82633A004352", "Bob", "Jones", "202-555-0132", "02134-12", "4 Mar 2026", "13/02/2024"],
+        );
+        assert_eq!(input.customer_zip.as_deref(), Some("02134-12"));
+        assert_eq!(input.warranty_exp_date.as_deref(), Some("4 Mar 2026"));
+        assert_eq!(input.last_service_date.as_deref(), Some("13/02/2024"));
+    }
+
+    #[test]
+    fn zip_codes_are_restored_only_when_digits_were_lost() {
+        let cases = [
+            ("94107", Some("94107")),
+            ("501", Some("00501")),
+            ("2134", Some("02134")),
+            ("941071234", Some("94107-1234")),
+            ("21341234", Some("02134-1234")),
+            ("12", None),
+            ("941071", None),
+            ("94107-1234", None),
+            ("SW1A 1AA", None),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(restore_zip(value).as_deref(), expected, "{}", value);
+        }
+    }
+}
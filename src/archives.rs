@@ -0,0 +1,158 @@
+use crate::reader::FileFormat;
+use crate::storage::{display_file_name, generated_file_name, max_archive_bytes, max_upload_bytes};
+use flate2::read::GzDecoder;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+// Most files one archive may contain; bounds the child uploads one request creates.
+const MAX_ARCHIVE_FILES: usize = 500;
+
+/// Compressed upload formats whose inner files are imported as child uploads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Zip,
+    /// A single gzip-compressed file such as `export.csv.gz`
+    Gzip,
+    TarGz,
+}
+
+impl ArchiveFormat {
+    pub fn from_path(path: &str) -> Option<Self> {
+        let name = path.to_ascii_lowercase();
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveFormat::TarGz)
+        } else if name.ends_with(".gz") {
+            Some(ArchiveFormat::Gzip)
+        } else if name.ends_with(".zip") {
+            Some(ArchiveFormat::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+/// A CSV or Excel file extracted from an archive.
+#[derive(Debug, Clone)]
+pub struct ExtractedFile {
+    /// Name of the file inside the archive, for display
+    pub filename: String,
+    pub path: PathBuf,
+}
+
+/// Extracts the CSV and Excel files of an archive into `dest_dir` under generated
+/// names, in archive order. Directories, hidden files and other file types are
+/// skipped. Each file is held to `MAX_UPLOAD_BYTES` once decompressed, and all of
+/// them together to `MAX_ARCHIVE_BYTES`; on any error the files already extracted
+/// are removed.
+pub fn extract_archive(
+    archive_path: &Path,
+    archive_name: &str,
+    format: ArchiveFormat,
+    dest_dir: &Path,
+) -> anyhow::Result<Vec<ExtractedFile>> {
+    let mut extracted = Vec::new();
+    let result = extract_into(archive_path, archive_name, format, dest_dir, &mut extracted);
+    if let Err(e) = result {
+        for file in &extracted {
+            let _ = std::fs::remove_file(&file.path);
+        }
+        return Err(e);
+    }
+    if extracted.is_empty() {
+        return Err(anyhow::anyhow!("The archive contains no CSV or Excel files"));
+    }
+    Ok(extracted)
+}
+
+fn extract_into(
+    archive_path: &Path,
+    archive_name: &str,
+    format: ArchiveFormat,
+    dest_dir: &Path,
+    extracted: &mut Vec<ExtractedFile>,
+) -> anyhow::Result<()> {
+    let file = File::open(archive_path)?;
+    let mut budget = ExtractionBudget {
+        file_bytes: max_upload_bytes(),
+        archive_bytes: max_archive_bytes(),
+        extracted_bytes: 0,
+    };
+    match format {
+        ArchiveFormat::Zip => {
+            let mut archive = zip::ZipArchive::new(file)?;
+            for i in 0..archive.len() {
+                let mut entry = archive.by_index(i)?;
+                if entry.is_file() {
+                    let name = entry.name().to_string();
+                    extract_entry(&name, &mut entry, dest_dir, extracted, &mut budget)?;
+                }
+            }
+        }
+        ArchiveFormat::Gzip => {
+            // "export.csv.gz" holds "export.csv"
+            let name = &archive_name[..archive_name.len() - ".gz".len()];
+            extract_entry(name, &mut GzDecoder::new(file), dest_dir, extracted, &mut budget)?;
+        }
+        ArchiveFormat::TarGz => {
+            let mut archive = tar::Archive::new(GzDecoder::new(file));
+            for entry in archive.entries()? {
+                let mut entry = entry?;
+                if entry.header().entry_type().is_file() {
+                    let name = entry.path()?.to_string_lossy().to_string();
+                    extract_entry(&name, &mut entry, dest_dir, extracted, &mut budget)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// Decompressed size limits for one file and for all of an archive's files
+struct ExtractionBudget {
+    file_bytes: u64,
+    archive_bytes: u64,
+    extracted_bytes: u64,
+}
+
+fn extract_entry(
+    name: &str,
+    reader: &mut dyn Read,
+    dest_dir: &Path,
+    extracted: &mut Vec<ExtractedFile>,
+    budget: &mut ExtractionBudget,
+) -> anyhow::Result<()> {
+    let filename = display_file_name(name);
+    // macOS adds "__MACOSX/._name" resource forks alongside the real files
+    if filename.starts_with('.') || name.contains("__MACOSX") || FileFormat::from_path(&filename).is_none() {
+        return Ok(());
+    }
+    if extracted.len() >= MAX_ARCHIVE_FILES {
+        return Err(anyhow::anyhow!("The archive contains more than {} files", MAX_ARCHIVE_FILES));
+    }
+
+    let path = dest_dir.join(generated_file_name(&filename));
+    let mut out = File::create(&path)?;
+    extracted.push(ExtractedFile {
+        filename: filename.clone(),
+        path,
+    });
+
+    let limit = budget.file_bytes.min(budget.archive_bytes - budget.extracted_bytes);
+    let written = io::copy(&mut reader.take(limit + 1), &mut out)?;
+    if written > budget.file_bytes {
+        return Err(anyhow::anyhow!(
+            "{} is larger than {} bytes once decompressed",
+            filename,
+            budget.file_bytes
+        ));
+    }
+    budget.extracted_bytes += written;
+    if budget.extracted_bytes > budget.archive_bytes {
+        return Err(anyhow::anyhow!(
+            "The archive's files are larger than {} bytes together once decompressed",
+            budget.archive_bytes
+        ));
+    }
+    Ok(())
+}
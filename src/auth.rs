@@ -55,7 +55,7 @@ pub fn verify_token(token: &str) -> anyhow::Result<Claims> {
 pub async fn get_dealer_from_token(token: &str, pool: &PgPool) -> anyhow::Result<Dealer> {
     let claims = verify_token(token)?;
     let dealer = sqlx::query_as::<_, Dealer>(
-        "SELECT id, name, email, password_hash, zip_code, created_at FROM dealers WHERE id = $1"
+        "SELECT id, name, email, password_hash, zip_code, created_at, date_order, default_country FROM dealers WHERE id = $1"
     )
     .bind(claims.dealer_id)
     .fetch_one(pool)
@@ -0,0 +1,266 @@
+use encoding_rs::{CoderResult, Decoder, Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+// Bytes examined for a UTF-16 file without a byte order mark
+const UTF16_SAMPLE_BYTES: usize = 4096;
+const CHUNK_BYTES: usize = 64 * 1024;
+
+/// Text encodings recognized in CSV uploads, stored as `uploads.encoding`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextEncoding {
+    Utf8,
+    /// UTF-8 starting with a byte order mark, as Excel writes "CSV UTF-8"
+    Utf8Bom,
+    Utf16Le,
+    Utf16Be,
+    /// Western European Windows text, as exported by most Windows software
+    Windows1252,
+    Iso8859_1,
+}
+
+impl TextEncoding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TextEncoding::Utf8 => "utf-8",
+            TextEncoding::Utf8Bom => "utf-8-bom",
+            TextEncoding::Utf16Le => "utf-16le",
+            TextEncoding::Utf16Be => "utf-16be",
+            TextEncoding::Windows1252 => "windows-1252",
+            TextEncoding::Iso8859_1 => "iso-8859-1",
+        }
+    }
+
+    // ISO-8859-1 and Windows-1252 differ only in 0x80-0x9F, which is only detected
+    // as ISO-8859-1 when absent, so one decoder serves both.
+    fn encoding(&self) -> &'static Encoding {
+        match self {
+            TextEncoding::Utf8 | TextEncoding::Utf8Bom => UTF_8,
+            TextEncoding::Utf16Le => UTF_16LE,
+            TextEncoding::Utf16Be => UTF_16BE,
+            TextEncoding::Windows1252 | TextEncoding::Iso8859_1 => WINDOWS_1252,
+        }
+    }
+}
+
+/// Works out a text file's encoding: from its byte order mark if it has one, else
+/// UTF-16 if every other byte of its start is zero, else UTF-8 if the whole file
+/// is valid UTF-8. Anything else is read as Windows-1252 when it uses the bytes
+/// 0x80-0x9F (curly quotes, the euro sign), or as ISO-8859-1 when it doesn't.
+pub fn detect_encoding(path: &Path) -> io::Result<TextEncoding> {
+    let mut file = File::open(path)?;
+    let mut chunk = vec![0; CHUNK_BYTES];
+    let mut len = read_full(&mut file, &mut chunk)?;
+
+    if chunk[..len].starts_with(&[0xEF, 0xBB, 0xBF]) {
+        return Ok(TextEncoding::Utf8Bom);
+    }
+    if chunk[..len].starts_with(&[0xFF, 0xFE]) {
+        return Ok(TextEncoding::Utf16Le);
+    }
+    if chunk[..len].starts_with(&[0xFE, 0xFF]) {
+        return Ok(TextEncoding::Utf16Be);
+    }
+    if let Some(encoding) = sniff_utf16(&chunk[..len.min(UTF16_SAMPLE_BYTES)]) {
+        return Ok(encoding);
+    }
+
+    // Validates a chunk at a time; a character split across chunks is carried over
+    let mut valid_utf8 = true;
+    let mut uses_c1 = false;
+    let mut carry = 0;
+    while len > 0 {
+        uses_c1 |= chunk[carry..len].iter().any(|b| (0x80..=0x9F).contains(b));
+        if valid_utf8 {
+            match std::str::from_utf8(&chunk[..len]) {
+                Ok(_) => carry = 0,
+                Err(e) if e.error_len().is_none() => carry = len - e.valid_up_to(),
+                Err(_) => {
+                    valid_utf8 = false;
+                    carry = 0;
+                }
+            }
+        }
+        if !valid_utf8 && uses_c1 {
+            break;
+        }
+        chunk.copy_within(len - carry..len, 0);
+        let read = read_full(&mut file, &mut chunk[carry..])?;
+        if read == 0 {
+            // A character cut off by the end of the file isn't valid UTF-8
+            valid_utf8 &= carry == 0;
+            break;
+        }
+        len = carry + read;
+    }
+
+    Ok(match (valid_utf8, uses_c1) {
+        (true, _) => TextEncoding::Utf8,
+        (false, true) => TextEncoding::Windows1252,
+        (false, false) => TextEncoding::Iso8859_1,
+    })
+}
+
+/// Opens a text file as UTF-8, transcoding it and dropping any byte order mark.
+pub fn open_utf8(path: &Path, encoding: TextEncoding) -> io::Result<Box<dyn Read + Send>> {
+    let file = File::open(path)?;
+    if encoding == TextEncoding::Utf8 {
+        return Ok(Box::new(file));
+    }
+    Ok(Box::new(DecodingReader {
+        inner: file,
+        decoder: encoding.encoding().new_decoder_with_bom_removal(),
+        input: vec![0; CHUNK_BYTES],
+        input_start: 0,
+        input_end: 0,
+        output: vec![0; CHUNK_BYTES * 3],
+        output_start: 0,
+        output_end: 0,
+        eof: false,
+        done: false,
+    }))
+}
+
+// Text without a BOM is taken as UTF-16 when nearly all of its high (LE: odd, BE:
+// even) bytes are zero, as they are for the ASCII that headers are written in.
+fn sniff_utf16(sample: &[u8]) -> Option<TextEncoding> {
+    let pairs = sample.len() / 2;
+    if pairs < 8 {
+        return None;
+    }
+    let zero_even = sample.iter().step_by(2).filter(|b| **b == 0).count();
+    let zero_odd = sample.iter().skip(1).step_by(2).filter(|b| **b == 0).count();
+    if zero_odd * 10 >= pairs * 9 && zero_even == 0 {
+        Some(TextEncoding::Utf16Le)
+    } else if zero_even * 10 >= pairs * 9 && zero_odd == 0 {
+        Some(TextEncoding::Utf16Be)
+    } else {
+        None
+    }
+}
+
+// Fills `buf` unless the end of the file comes first.
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+// Streams a file through an encoding_rs decoder. Unmappable bytes become U+FFFD.
+struct DecodingReader<R> {
+    inner: R,
+    decoder: Decoder,
+    input: Vec<u8>,
+    input_start: usize,
+    input_end: usize,
+    output: Vec<u8>,
+    output_start: usize,
+    output_end: usize,
+    eof: bool,
+    done: bool,
+}
+
+impl<R: Read> Read for DecodingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.output_start < self.output_end {
+                let n = buf.len().min(self.output_end - self.output_start);
+                buf[..n].copy_from_slice(&self.output[self.output_start..self.output_start + n]);
+                self.output_start += n;
+                return Ok(n);
+            }
+            if self.done {
+                return Ok(0);
+            }
+            if self.input_start == self.input_end && !self.eof {
+                self.input_end = self.inner.read(&mut self.input)?;
+                self.input_start = 0;
+                self.eof = self.input_end == 0;
+            }
+
+            let (result, read, written, _) = self.decoder.decode_to_utf8(
+                &self.input[self.input_start..self.input_end],
+                &mut self.output,
+                self.eof,
+            );
+            self.input_start += read;
+            self.output_start = 0;
+            self.output_end = written;
+            if self.eof && result == CoderResult::InputEmpty {
+                self.done = true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    // Writes `bytes` to a file of its own in the temp directory
+    fn temp_file(bytes: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("charset-test-{}.csv", uuid::Uuid::new_v4()));
+        std::fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    fn utf16(text: &str, big_endian: bool) -> Vec<u8> {
+        text.encode_utf16()
+            .flat_map(|unit| if big_endian { unit.to_be_bytes() } else { unit.to_le_bytes() })
+            .collect()
+    }
+
+    #[test]
+    fn encodings_are_detected() {
+        let text = "vin,name\n1HGCM82633A004352,Zoë\n";
+        let mut long_utf8 = vec![b'a'; CHUNK_BYTES - 1];
+        long_utf8.extend("é\n".as_bytes());
+        let cases = [
+            (text.as_bytes().to_vec(), TextEncoding::Utf8),
+            (b"vin,name\n".to_vec(), TextEncoding::Utf8),
+            (Vec::new(), TextEncoding::Utf8),
+            ([&[0xEF, 0xBB, 0xBF], text.as_bytes()].concat(), TextEncoding::Utf8Bom),
+            ([vec![0xFF, 0xFE], utf16(text, false)].concat(), TextEncoding::Utf16Le),
+            ([vec![0xFE, 0xFF], utf16(text, true)].concat(), TextEncoding::Utf16Be),
+            (utf16(text, false), TextEncoding::Utf16Le),
+            (utf16(text, true), TextEncoding::Utf16Be),
+            (b"name\nCaf\xe9 \x93Le Bistro\x94 \x80 5\n".to_vec(), TextEncoding::Windows1252),
+            (b"name\nCaf\xe9\n".to_vec(), TextEncoding::Iso8859_1),
+            // A character split across reads is still UTF-8
+            (long_utf8, TextEncoding::Utf8),
+            // A character cut off by the end of the file isn't
+            (b"name\nCaf\xc3".to_vec(), TextEncoding::Iso8859_1),
+        ];
+        for (i, (bytes, expected)) in cases.into_iter().enumerate() {
+            let path = temp_file(&bytes);
+            let detected = detect_encoding(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(detected, expected, "case {}", i);
+        }
+    }
+
+    #[test]
+    fn files_are_transcoded_to_utf8_without_bom() {
+        let text = "vin,name\n1HGCM82633A004352,Zoë “Z” 5€\n";
+        let cases = [
+            ([&[0xEF, 0xBB, 0xBF], text.as_bytes()].concat(), TextEncoding::Utf8Bom),
+            ([vec![0xFF, 0xFE], utf16(text, false)].concat(), TextEncoding::Utf16Le),
+            (utf16(text, true), TextEncoding::Utf16Be),
+            (b"vin,name\n1HGCM82633A004352,Zo\xeb \x93Z\x94 5\x80\n".to_vec(), TextEncoding::Windows1252),
+        ];
+        for (bytes, encoding) in cases {
+            let path = temp_file(&bytes);
+            let mut decoded = String::new();
+            open_utf8(&path, encoding).unwrap().read_to_string(&mut decoded).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(decoded, text, "{:?}", encoding);
+        }
+    }
+}
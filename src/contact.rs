@@ -0,0 +1,133 @@
+use email_address::EmailAddress;
+use phonenumber::country::Id as CountryId;
+use phonenumber::Mode;
+use std::str::FromStr;
+
+/// Whether a customer can be reached by phone, stored as `vehicles.contact_status`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContactStatus {
+    /// The phone number is a valid number, stored in E.164 form
+    Valid,
+    /// A phone number was given but isn't a valid number; it is stored as typed
+    Invalid,
+    /// No phone number was given
+    Missing,
+}
+
+impl ContactStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContactStatus::Valid => "valid",
+            ContactStatus::Invalid => "invalid",
+            ContactStatus::Missing => "missing",
+        }
+    }
+}
+
+/// A phone number in E.164 form (`+15551234567`) with any extension split off.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NormalizedPhone {
+    pub e164: String,
+    pub extension: Option<String>,
+}
+
+/// Parses the `dealers.default_country` setting, an ISO 3166 alpha-2 code such as `US`.
+pub fn parse_country(code: &str) -> Option<CountryId> {
+    CountryId::from_str(&code.trim().to_ascii_uppercase()).ok()
+}
+
+/// Normalizes a phone number to E.164. Numbers without a `+` country prefix are read
+/// as national numbers of `default_country`. Extensions written as `ext 2` or `x2`
+/// are returned separately.
+pub fn normalize_phone(raw: &str, default_country: CountryId) -> Result<NormalizedPhone, String> {
+    let number = phonenumber::parse(Some(default_country), raw.trim())
+        .map_err(|_| "Not a phone number; kept as typed".to_string())?;
+    if !phonenumber::is_valid(&number) {
+        return Err("Not a valid phone number; kept as typed".to_string());
+    }
+    Ok(NormalizedPhone {
+        e164: number.format().mode(Mode::E164).to_string(),
+        extension: number.extension().map(|ext| ext.to_string()),
+    })
+}
+
+/// Checks email syntax and lowercases the domain, which is case-insensitive.
+pub fn normalize_email(raw: &str) -> Result<String, String> {
+    let email = EmailAddress::from_str(raw.trim()).map_err(|_| "Invalid email address; value ignored".to_string())?;
+    if !email.domain().contains('.') {
+        return Err("Invalid email address; value ignored".to_string());
+    }
+    Ok(format!("{}@{}", email.local_part(), email.domain().to_ascii_lowercase()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phone_numbers_are_formatted_as_e164() {
+        let cases = [
+            ("(202) 555-0132", "US", "+12025550132", None),
+            ("202.555.0132", "US", "+12025550132", None),
+            ("1-202-555-0132", "US", "+12025550132", None),
+            ("+1 202 555 0132", "GB", "+12025550132", None),
+            ("202-555-0132 ext 42", "US", "+12025550132", Some("42")),
+            ("202-555-0132 x7", "US", "+12025550132", Some("7")),
+            ("020 7946 0958", "GB", "+442079460958", None),
+            ("+44 20 7946 0958", "US", "+442079460958", None),
+            ("030 123456", "DE", "+4930123456", None),
+            ("  (416) 555-0199  ", "CA", "+14165550199", None),
+        ];
+        for (raw, country, e164, extension) in cases {
+            let phone = normalize_phone(raw, parse_country(country).unwrap()).unwrap();
+            assert_eq!(
+                phone,
+                NormalizedPhone {
+                    e164: e164.to_string(),
+                    extension: extension.map(str::to_string),
+                },
+                "{} in {}",
+                raw,
+                country
+            );
+        }
+    }
+
+    #[test]
+    fn unusable_phone_numbers_are_refused() {
+        let cases = [
+            ("12", "Not a phone number; kept as typed"),
+            ("call me", "Not a phone number; kept as typed"),
+            ("(000) 555-0132", "Not a valid phone number; kept as typed"),
+            ("555-0132", "Not a valid phone number; kept as typed"),
+        ];
+        for (raw, reason) in cases {
+            assert_eq!(normalize_phone(raw, CountryId::US).unwrap_err(), reason, "{}", raw);
+        }
+    }
+
+    #[test]
+    fn countries_are_parsed_case_insensitively() {
+        assert_eq!(parse_country(" us "), Some(CountryId::US));
+        assert_eq!(parse_country("GB"), Some(CountryId::GB));
+        assert_eq!(parse_country("XX"), None);
+    }
+
+    #[test]
+    fn emails_are_checked_and_domains_lowercased() {
+        let cases = [
+            (" Jane.Doe@Example.COM ", Ok("Jane.Doe@example.com")),
+            ("jane+leads@mail.example.co.uk", Ok("jane+leads@mail.example.co.uk")),
+            ("jane@localhost", Err(())),
+            ("jane.example.com", Err(())),
+            ("jane@@example.com", Err(())),
+            ("", Err(())),
+        ];
+        for (raw, expected) in cases {
+            let expected = expected
+                .map(str::to_string)
+                .map_err(|_| "Invalid email address; value ignored".to_string());
+            assert_eq!(normalize_email(raw), expected, "{}", raw);
+        }
+    }
+}
@@ -0,0 +1,266 @@
+use chrono::NaiveDate;
+
+/// How a dealer's files write all-numeric dates such as `03/04/2025`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DateOrder {
+    /// `MM/DD/YYYY`, as in the US
+    #[default]
+    MonthFirst,
+    /// `DD/MM/YYYY`, as in most other countries
+    DayFirst,
+}
+
+impl DateOrder {
+    /// Parses the `dealers.date_order` setting (`mdy` or `dmy`).
+    pub fn from_setting(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "mdy" => Some(DateOrder::MonthFirst),
+            "dmy" => Some(DateOrder::DayFirst),
+            _ => None,
+        }
+    }
+
+    pub fn as_setting(&self) -> &'static str {
+        match self {
+            DateOrder::MonthFirst => "mdy",
+            DateOrder::DayFirst => "dmy",
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            DateOrder::MonthFirst => "month-first",
+            DateOrder::DayFirst => "day-first",
+        }
+    }
+}
+
+/// A date read from an upload. `ambiguous` is set when the day and month could
+/// be swapped, in which case the dealer's `DateOrder` decided between them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParsedDate {
+    pub date: NaiveDate,
+    pub ambiguous: bool,
+}
+
+impl ParsedDate {
+    fn exact(date: NaiveDate) -> Self {
+        ParsedDate { date, ambiguous: false }
+    }
+}
+
+const MONTHS: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+
+/// Parses the date formats dealers' systems commonly export:
+///
+/// - ISO `2025-03-15`, `2025/03/15`, `20250315`, with or without a time (`2025-03-15 00:00:00`, `2025-03-15T00:00:00Z`)
+/// - numeric day/month dates `03/15/2025`, `15.03.2025`, `3-15-25`, read in `order` when ambiguous
+/// - month names `15 Mar 2025`, `March 15, 2025`, `15-Mar-25`
+/// - Excel serial day numbers such as `45731`
+pub fn parse_date(value: &str, order: DateOrder) -> Option<ParsedDate> {
+    let value = strip_time(value.trim());
+    if value.is_empty() {
+        return None;
+    }
+
+    if let Some(date) = parse_excel_serial(value) {
+        return Some(ParsedDate::exact(date));
+    }
+    if value.len() == 8 && value.bytes().all(|b| b.is_ascii_digit()) {
+        return NaiveDate::parse_from_str(value, "%Y%m%d").ok().map(ParsedDate::exact);
+    }
+
+    let parts: Vec<&str> = value
+        .split(['-', '/', '.', ',', ' '])
+        .filter(|p| !p.is_empty())
+        .collect();
+    if parts.len() != 3 {
+        return None;
+    }
+
+    if parts.iter().any(|p| p.chars().any(|c| c.is_ascii_alphabetic())) {
+        return parse_month_name(&parts).map(ParsedDate::exact);
+    }
+    parse_numeric(&parts, order)
+}
+
+/// Explains how an ambiguous date was read, for the upload's row report.
+pub fn ambiguity_note(parsed: &ParsedDate, order: DateOrder) -> String {
+    format!(
+        "Ambiguous date; read as {} ({}) per the dealer's date order setting",
+        order.describe(),
+        parsed.date.format("%Y-%m-%d")
+    )
+}
+
+// Drops a trailing time of day: "2025-03-15T10:00:00Z", "03/15/2025 10:00 AM".
+fn strip_time(value: &str) -> &str {
+    if let Some(t) = value.find('T') {
+        if t >= 8 && value[..t].bytes().all(|b| b.is_ascii_digit() || b == b'-') {
+            return &value[..t];
+        }
+    }
+    match value.find(':') {
+        Some(colon) => value[..colon]
+            .rfind(char::is_whitespace)
+            .map(|space| value[..space].trim_end())
+            .unwrap_or(value),
+        None => value,
+    }
+}
+
+fn parse_numeric(parts: &[&str], order: DateOrder) -> Option<ParsedDate> {
+    if !parts.iter().all(|p| p.bytes().all(|b| b.is_ascii_digit())) {
+        return None;
+    }
+    let numbers: Vec<u32> = parts.iter().map(|p| p.parse().ok()).collect::<Option<_>>()?;
+
+    if parts[0].len() == 4 {
+        return NaiveDate::from_ymd_opt(numbers[0] as i32, numbers[1], numbers[2]).map(ParsedDate::exact);
+    }
+    if parts[0].len() > 2 || parts[1].len() > 2 || !matches!(parts[2].len(), 2 | 4) {
+        return None;
+    }
+
+    let year = expand_year(numbers[2], parts[2].len());
+    let (first, second) = (numbers[0], numbers[1]);
+    let (month, day, ambiguous) = if first > 12 {
+        (second, first, false)
+    } else if second > 12 {
+        (first, second, false)
+    } else {
+        let ambiguous = first != second;
+        match order {
+            DateOrder::MonthFirst => (first, second, ambiguous),
+            DateOrder::DayFirst => (second, first, ambiguous),
+        }
+    };
+
+    NaiveDate::from_ymd_opt(year, month, day).map(|date| ParsedDate { date, ambiguous })
+}
+
+fn parse_month_name(parts: &[&str]) -> Option<NaiveDate> {
+    let month_index = parts.iter().position(|p| p.chars().all(|c| c.is_ascii_alphabetic()))?;
+    let name = parts[month_index].to_ascii_lowercase();
+    let month = MONTHS.iter().position(|m| name.len() >= 3 && m.starts_with(&name[..3]))? as u32 + 1;
+
+    let numbers: Vec<&str> = parts
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| *i != month_index)
+        .map(|(_, p)| *p)
+        .collect();
+    if !numbers.iter().all(|p| p.bytes().all(|b| b.is_ascii_digit())) {
+        return None;
+    }
+
+    // "2025 Mar 15" has the year first; otherwise it is last ("15 Mar 2025", "Mar 15 2025")
+    let (day, year) = if numbers[0].len() == 4 {
+        (numbers[1], numbers[0])
+    } else {
+        (numbers[0], numbers[1])
+    };
+    if day.len() > 2 || !matches!(year.len(), 2 | 4) {
+        return None;
+    }
+    let year = expand_year(year.parse().ok()?, year.len());
+    NaiveDate::from_ymd_opt(year, month, day.parse().ok()?)
+}
+
+// Two-digit years follow chrono's `%y`: 00-68 are 2000s, 69-99 are 1900s.
+fn expand_year(year: u32, digits: usize) -> i32 {
+    match (digits, year) {
+        (2, 0..=68) => 2000 + year as i32,
+        (2, _) => 1900 + year as i32,
+        _ => year as i32,
+    }
+}
+
+// Excel stores dates as days since 1899-12-30. Only serials between 1927 and 2173
+// are accepted, so stray small numbers aren't mistaken for dates.
+fn parse_excel_serial(value: &str) -> Option<NaiveDate> {
+    let serial = value.parse::<f64>().ok().filter(|n| (10_000.0..=100_000.0).contains(n))?;
+    NaiveDate::from_ymd_opt(1899, 12, 30)?.checked_add_signed(chrono::Duration::days(serial.trunc() as i64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ymd(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn ambiguous_numeric_dates_follow_the_date_order() {
+        // (value, month-first reading, day-first reading, ambiguous)
+        let cases = [
+            ("03/04/2025", ymd(2025, 3, 4), ymd(2025, 4, 3), true),
+            ("3-4-25", ymd(2025, 3, 4), ymd(2025, 4, 3), true),
+            ("03/04/2025 10:30 AM", ymd(2025, 3, 4), ymd(2025, 4, 3), true),
+            ("12.11.2024", ymd(2024, 12, 11), ymd(2024, 11, 12), true),
+            ("05/05/2025", ymd(2025, 5, 5), ymd(2025, 5, 5), false),
+            ("03/15/2025", ymd(2025, 3, 15), ymd(2025, 3, 15), false),
+            ("15/03/2025", ymd(2025, 3, 15), ymd(2025, 3, 15), false),
+            ("15.03.25", ymd(2025, 3, 15), ymd(2025, 3, 15), false),
+        ];
+        for (value, month_first, day_first, ambiguous) in cases {
+            for (order, date) in [(DateOrder::MonthFirst, month_first), (DateOrder::DayFirst, day_first)] {
+                assert_eq!(parse_date(value, order), Some(ParsedDate { date, ambiguous }), "{} {:?}", value, order);
+            }
+        }
+    }
+
+    #[test]
+    fn unambiguous_formats_ignore_the_date_order() {
+        let cases = [
+            ("2025-03-04", ymd(2025, 3, 4)),
+            ("2025/03/04", ymd(2025, 3, 4)),
+            ("20250304", ymd(2025, 3, 4)),
+            ("2025-03-04 00:00:00", ymd(2025, 3, 4)),
+            ("2025-03-04T10:30:00Z", ymd(2025, 3, 4)),
+            ("4 Mar 2025", ymd(2025, 3, 4)),
+            ("March 4, 2025", ymd(2025, 3, 4)),
+            ("04-MAR-25", ymd(2025, 3, 4)),
+            ("2025 Mar 4", ymd(2025, 3, 4)),
+            ("45720", ymd(2025, 3, 4)),
+            ("45720.5", ymd(2025, 3, 4)),
+            ("1/1/69", ymd(1969, 1, 1)),
+            ("1/1/68", ymd(2068, 1, 1)),
+        ];
+        for (value, date) in cases {
+            for order in [DateOrder::MonthFirst, DateOrder::DayFirst] {
+                assert_eq!(parse_date(value, order), Some(ParsedDate { date, ambiguous: false }), "{} {:?}", value, order);
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_dates_are_not_parsed() {
+        let cases = [
+            "", "   ", "13/13/2025", "02/30/2025", "2025-02-30", "20250230", "Smarch 4, 2025", "4 Mar", "03/04",
+            "03/04/025", "123/04/2025", "1234", "not a date",
+        ];
+        for value in cases {
+            assert_eq!(parse_date(value, DateOrder::MonthFirst), None, "{}", value);
+        }
+    }
+
+    #[test]
+    fn ambiguity_note_names_the_order_used() {
+        let parsed = parse_date("03/04/2025", DateOrder::DayFirst).unwrap();
+        assert_eq!(
+            ambiguity_note(&parsed, DateOrder::DayFirst),
+            "Ambiguous date; read as day-first (2025-04-03) per the dealer's date order setting"
+        );
+    }
+
+    #[test]
+    fn date_order_settings_round_trip() {
+        for order in [DateOrder::MonthFirst, DateOrder::DayFirst] {
+            assert_eq!(DateOrder::from_setting(order.as_setting()), Some(order));
+        }
+        assert_eq!(DateOrder::from_setting(" DMY "), Some(DateOrder::DayFirst));
+        assert_eq!(DateOrder::from_setting("ymd"), None);
+    }
+}
@@ -1,17 +1,37 @@
+use crate::adapters::{adapter_by_id, adapters, GENERIC_ADAPTER};
+use crate::archives::ArchiveFormat;
 use crate::auth::{create_token, hash_password, verify_password, verify_token};
+use crate::contact::parse_country;
+use crate::dates::DateOrder;
+use crate::ingest::ImportSettings;
+use crate::jobs::{enqueue_upload, JobMode};
+use crate::pipeline::{preview_upload_file, rescore_vehicles, validate_batch_records, write_batch_file};
+use crate::quotas::dealer_quotas;
+use crate::reader::FileFormat;
+use crate::scoring::{strategy_by_id, LeadScorer, ScoringSettings, DEFAULT_STRATEGY, SCORING_VERSION, STRATEGIES};
+use crate::uploads::{
+    create_upload, record_audit, refresh_archive_upload, refresh_parent_upload, remove_upload, CreateUploadError, NewUpload,
+};
+use crate::telematics::{parse_readings, recent_readings, store_readings, validate_reading};
+use crate::storage::{dealer_upload_dir, display_file_name, generated_file_name, max_upload_bytes};
 use crate::models::*;
-use crate::scoring::calculate_lead_scores;
 use axum::{
+    body::Bytes,
     extract::{Multipart, Path as AxumPath, Query, State},
-    http::{HeaderMap, StatusCode},
-    response::Json,
+    http::{header, HeaderMap, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Json,
+    },
 };
 use chrono::NaiveDate;
-use csv::ReaderBuilder;
-use sqlx::{PgPool, Row};
+use futures::{Stream, TryStreamExt};
+use csv::{ReaderBuilder, StringRecord, WriterBuilder};
+use sqlx::{postgres::PgRow, PgPool, Postgres, QueryBuilder, Row};
 use std::collections::HashMap;
-use std::fs;
-use std::path::Path as StdPath;
+use std::convert::Infallible;
+use std::path::{Path as StdPath, PathBuf};
+use std::time::Duration;
 use tokio::fs::File;
 use tokio::io::AsyncWriteExt;
 
@@ -30,7 +50,7 @@ async fn get_dealer_from_request(
     let claims = verify_token(&token).map_err(|_| StatusCode::UNAUTHORIZED)?;
     
     let dealer = sqlx::query_as::<_, Dealer>(
-        "SELECT id, name, email, password_hash, zip_code, created_at FROM dealers WHERE id = $1"
+        "SELECT id, name, email, password_hash, zip_code, created_at, date_order, default_country FROM dealers WHERE id = $1"
     )
     .bind(claims.dealer_id)
     .fetch_one(pool)
@@ -47,7 +67,7 @@ pub async fn register_dealer(
 ) -> Result<Json<ApiResponse<DealerResponse>>, StatusCode> {
     // Check if email already exists
     let existing = sqlx::query_as::<_, Dealer>(
-        "SELECT id, name, email, password_hash, zip_code, created_at FROM dealers WHERE email = $1"
+        "SELECT id, name, email, password_hash, zip_code, created_at, date_order, default_country FROM dealers WHERE email = $1"
     )
     .bind(&payload.email)
     .fetch_optional(&pool)
@@ -68,7 +88,7 @@ pub async fn register_dealer(
 
     // Insert dealer
     let dealer = sqlx::query_as::<_, Dealer>(
-        "INSERT INTO dealers (name, email, password_hash, zip_code) VALUES ($1, $2, $3, $4) RETURNING id, name, email, password_hash, zip_code, created_at"
+        "INSERT INTO dealers (name, email, password_hash, zip_code) VALUES ($1, $2, $3, $4) RETURNING id, name, email, password_hash, zip_code, created_at, date_order, default_country"
     )
     .bind(&payload.name)
     .bind(&payload.email)
@@ -86,7 +106,7 @@ pub async fn login_dealer(
     Json(payload): Json<LoginRequest>,
 ) -> Result<Json<ApiResponse<LoginResponse>>, StatusCode> {
     let dealer = sqlx::query_as::<_, Dealer>(
-        "SELECT id, name, email, password_hash, zip_code, created_at FROM dealers WHERE email = $1"
+        "SELECT id, name, email, password_hash, zip_code, created_at, date_order, default_country FROM dealers WHERE email = $1"
     )
     .bind(&payload.email)
     .fetch_optional(&pool)
@@ -118,11 +138,165 @@ pub async fn login_dealer(
 pub async fn get_dealer_profile(
     State(pool): State<PgPool>,
     headers: HeaderMap,
+) -> Result<Json<ApiResponse<DealerProfile>>, StatusCode> {
+    let dealer = get_dealer_from_request(&headers, &pool).await?;
+    let quotas = dealer_quotas(&pool, dealer.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(ApiResponse::success(DealerProfile {
+        dealer: DealerResponse::from(dealer),
+        quotas,
+    })))
+}
+
+pub async fn update_dealer_profile(
+    State(pool): State<PgPool>,
+    headers: HeaderMap,
+    Json(payload): Json<UpdateDealerRequest>,
 ) -> Result<Json<ApiResponse<DealerResponse>>, StatusCode> {
     let dealer = get_dealer_from_request(&headers, &pool).await?;
+
+    let date_order = match payload.date_order.as_deref() {
+        Some(value) => match DateOrder::from_setting(value) {
+            Some(order) => Some(order.as_setting()),
+            None => {
+                return Ok(Json(ApiResponse {
+                    success: false,
+                    data: None,
+                    error: Some("date_order must be 'mdy' or 'dmy'".to_string()),
+                }))
+            }
+        },
+        None => None,
+    };
+    let default_country = match payload.default_country.as_deref() {
+        Some(value) => match parse_country(value) {
+            Some(country) => Some(country.as_ref().to_string()),
+            None => {
+                return Ok(Json(ApiResponse {
+                    success: false,
+                    data: None,
+                    error: Some("default_country must be an ISO 3166 two-letter country code".to_string()),
+                }))
+            }
+        },
+        None => None,
+    };
+
+    let dealer = sqlx::query_as::<_, Dealer>(
+        "UPDATE dealers SET date_order = COALESCE($1, date_order), default_country = COALESCE($2, default_country) WHERE id = $3 RETURNING id, name, email, password_hash, zip_code, created_at, date_order, default_country"
+    )
+    .bind(date_order)
+    .bind(default_country)
+    .bind(dealer.id)
+    .fetch_one(&pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
     Ok(Json(ApiResponse::success(DealerResponse::from(dealer))))
 }
 
+pub async fn get_scoring_settings(
+    State(pool): State<PgPool>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<ScoringSettings>>, StatusCode> {
+    let dealer = get_dealer_from_request(&headers, &pool).await?;
+    let settings = ScoringSettings::for_dealer(&pool, dealer.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(ApiResponse::success(settings)))
+}
+
+/// Replaces the dealer's scoring settings. Uploads imported or rescored from now on
+/// use them; existing leads keep their scores until their upload is reprocessed.
+pub async fn update_scoring_settings(
+    State(pool): State<PgPool>,
+    headers: HeaderMap,
+    Json(payload): Json<UpdateScoringRequest>,
+) -> Result<Json<ApiResponse<ScoringSettings>>, StatusCode> {
+    let dealer = get_dealer_from_request(&headers, &pool).await?;
+
+    let strategy = payload.strategy.as_deref().unwrap_or(DEFAULT_STRATEGY);
+    let refusal = match strategy_by_id(strategy, payload.config.clone()) {
+        None => Some(format!(
+            "Unknown scoring strategy '{}'. Available strategies: {}",
+            strategy,
+            STRATEGIES.join(", ")
+        )),
+        Some(_) => payload.config.validate().err(),
+    };
+    if let Some(message) = refusal {
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            error: Some(message),
+        }));
+    }
+
+    let updated_at = sqlx::query_scalar(
+        "INSERT INTO dealer_scoring_settings (dealer_id, strategy, config) VALUES ($1, $2, $3) \
+         ON CONFLICT (dealer_id) DO UPDATE SET strategy = EXCLUDED.strategy, config = EXCLUDED.config, updated_at = NOW() \
+         RETURNING updated_at"
+    )
+    .bind(dealer.id)
+    .bind(strategy.trim().to_ascii_lowercase())
+    .bind(sqlx::types::Json(&payload.config))
+    .fetch_one(&pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(ApiResponse::success(ScoringSettings {
+        strategy: strategy.trim().to_ascii_lowercase(),
+        config: payload.config,
+        updated_at: Some(updated_at),
+    })))
+}
+
+/// Returns the dealer to the default scoring settings.
+pub async fn reset_scoring_settings(
+    State(pool): State<PgPool>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<ScoringSettings>>, StatusCode> {
+    let dealer = get_dealer_from_request(&headers, &pool).await?;
+    sqlx::query("DELETE FROM dealer_scoring_settings WHERE dealer_id = $1")
+        .bind(dealer.id)
+        .execute(&pool)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(ApiResponse::success(ScoringSettings::default())))
+}
+
+/// Lists the scoring models that scored the dealer's current leads, newest first,
+/// with the model of its current settings even before it has scored anything.
+pub async fn list_scoring_models(
+    State(pool): State<PgPool>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<Vec<ScoringModel>>>, StatusCode> {
+    let dealer = get_dealer_from_request(&headers, &pool).await?;
+    let active_model = ScoringSettings::for_dealer(&pool, dealer.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .find_model(&pool)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let models = sqlx::query_as::<_, ScoringModel>(
+        "SELECT m.id, m.scoring_version, m.strategy, m.config, m.created_at, COALESCE(c.lead_count, 0) AS lead_count, COALESCE(m.id = $2, FALSE) AS active \
+         FROM scoring_models m LEFT JOIN ( \
+             SELECT sl.model_id, COUNT(*) AS lead_count FROM scored_leads sl JOIN vehicles v ON v.id = sl.vehicle_id \
+             WHERE v.dealer_id = $1 GROUP BY sl.model_id \
+         ) c ON c.model_id = m.id \
+         WHERE c.lead_count > 0 OR m.id = $2 ORDER BY m.id DESC"
+    )
+    .bind(dealer.id)
+    .bind(active_model)
+    .fetch_all(&pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(ApiResponse::success(models)))
+}
+
 // Upload handlers
 pub async fn upload_file(
     State(pool): State<PgPool>,
@@ -130,184 +304,609 @@ pub async fn upload_file(
     mut multipart: Multipart,
 ) -> Result<Json<ApiResponse<Upload>>, StatusCode> {
     let dealer = get_dealer_from_request(&headers, &pool).await?;
-    let mut filename = None;
-    let mut file_data = Vec::new();
-
-    while let Ok(Some(field)) = multipart.next_field().await {
-        let name = field.name().unwrap_or("");
-        if name == "file" {
-            filename = field.file_name().map(|s| s.to_string());
-            if let Ok(data) = field.bytes().await {
-                file_data = data.to_vec();
+    let UploadForm {
+        filename,
+        file_path,
+        all_or_nothing,
+        sheet_name,
+        adapter,
+        ..
+    } = read_upload_form(&mut multipart, &dealer_upload_dir(dealer.id)).await?;
+    if let Some(id) = adapter.as_deref().filter(|id| adapter_by_id(id).is_none()) {
+        let _ = tokio::fs::remove_file(&file_path).await;
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            error: Some(unknown_adapter_message(id)),
+        }));
+    }
+
+    let new_upload = NewUpload {
+        dealer_id: dealer.id,
+        filename,
+        file_path,
+        all_or_nothing,
+        sheet_name,
+        adapter,
+        source: "file",
+        watch_path: None,
+    };
+    let upload_id = match create_upload(&pool, new_upload).await {
+        Ok(id) => id,
+        Err(CreateUploadError::Rejected(message)) => {
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                error: Some(message),
+            }))
+        }
+        Err(CreateUploadError::Failed(_)) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+    };
+
+    let upload = find_dealer_upload(upload_id, dealer.id, &pool).await?;
+    Ok(Json(ApiResponse::success(upload)))
+}
+
+pub async fn preview_upload(
+    State(pool): State<PgPool>,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+    mut multipart: Multipart,
+) -> Result<Json<ApiResponse<UploadPreview>>, StatusCode> {
+    let dealer = get_dealer_from_request(&headers, &pool).await?;
+    let row_limit = params
+        .get("limit")
+        .and_then(|s| s.parse::<usize>().ok())
+        .unwrap_or(20)
+        .min(500);
+    let scoring = ScoringSettings::for_dealer(&pool, dealer.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .strategy()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    // The readers work on files, so stage the upload in the temp directory
+    let form = read_upload_form(&mut multipart, &std::env::temp_dir()).await?;
+    let temp_path = form.file_path.clone();
+    let temp_path_str = temp_path.to_string_lossy().to_string();
+    let refusal = if form.archive.is_some() {
+        Some("Archives can't be previewed; preview one of the files inside instead".to_string())
+    } else {
+        form.adapter.as_deref().filter(|id| adapter_by_id(id).is_none()).map(unknown_adapter_message)
+    };
+    if let Some(message) = refusal {
+        let _ = tokio::fs::remove_file(&temp_path).await;
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            error: Some(message),
+        }));
+    }
+
+    let settings = ImportSettings::for_dealer(&dealer);
+    let result = preview_upload_file(
+        &pool,
+        temp_path_str,
+        form.sheet_name,
+        form.adapter,
+        dealer.id,
+        settings,
+        scoring,
+        row_limit,
+    )
+    .await;
+    let _ = tokio::fs::remove_file(&temp_path).await;
+
+    match result {
+        Ok(preview) => Ok(Json(ApiResponse::success(preview))),
+        Err(e) => Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            error: Some(e.to_string()),
+        })),
+    }
+}
+
+struct UploadForm {
+    filename: String,
+    file_path: PathBuf,
+    all_or_nothing: bool,
+    sheet_name: Option<String>,
+    /// Adapter id picked by the dealer; `None` (or `auto`) detects it from the headers
+    adapter: Option<String>,
+    archive: Option<ArchiveFormat>,
+}
+
+// Streams the `file` field into `dest_dir` under a server-generated name,
+// rejecting it with 413 once it passes `MAX_UPLOAD_BYTES`.
+async fn read_upload_form(multipart: &mut Multipart, dest_dir: &StdPath) -> Result<UploadForm, StatusCode> {
+    let mut file = None;
+    let mut all_or_nothing = false;
+    let mut sheet_name = None;
+    let mut adapter = None;
+
+    let result = async {
+        while let Some(mut field) = multipart.next_field().await.map_err(|e| e.status())? {
+            let name = field.name().unwrap_or("");
+            if name == "file" {
+                let original = field.file_name().map(display_file_name).ok_or(StatusCode::BAD_REQUEST)?;
+                if FileFormat::from_path(&original).is_none() && ArchiveFormat::from_path(&original).is_none() {
+                    return Err(StatusCode::UNSUPPORTED_MEDIA_TYPE);
+                }
+                tokio::fs::create_dir_all(dest_dir)
+                    .await
+                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+                let path = dest_dir.join(generated_file_name(&original));
+                let mut out = File::create(&path).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+                file = Some((original, path));
+
+                let max_bytes = max_upload_bytes();
+                let mut written: u64 = 0;
+                while let Some(chunk) = field.chunk().await.map_err(|e| e.status())? {
+                    written += chunk.len() as u64;
+                    if written > max_bytes {
+                        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+                    }
+                    out.write_all(&chunk).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+                }
+                out.flush().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            } else if name == "all_or_nothing" {
+                let value = field.text().await.map_err(|e| e.status())?;
+                all_or_nothing = matches!(value.trim(), "true" | "1" | "yes");
+            } else if name == "sheet" {
+                let value = field.text().await.map_err(|e| e.status())?;
+                sheet_name = Some(value.trim().to_string()).filter(|s| !s.is_empty());
+            } else if name == "adapter" {
+                let value = field.text().await.map_err(|e| e.status())?;
+                adapter = Some(value.trim().to_string()).filter(|s| !s.is_empty() && !s.eq_ignore_ascii_case("auto"));
             }
         }
+        Ok(())
     }
+    .await;
 
-    let filename = filename.ok_or(StatusCode::BAD_REQUEST)?;
-    
-    // Create uploads directory if it doesn't exist
-    let uploads_dir = StdPath::new("uploads");
-    if !uploads_dir.exists() {
-        fs::create_dir_all(uploads_dir).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if let Err(status) = result {
+        if let Some((_, path)) = &file {
+            let _ = tokio::fs::remove_file(path).await;
+        }
+        return Err(status);
     }
 
-    // Save file
-    let file_path = uploads_dir.join(&filename);
-    let mut file = File::create(&file_path).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    file.write_all(&file_data).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let (filename, file_path) = file.ok_or(StatusCode::BAD_REQUEST)?;
+    let archive = ArchiveFormat::from_path(&filename);
+    Ok(UploadForm {
+        filename,
+        file_path,
+        all_or_nothing,
+        sheet_name,
+        adapter,
+        archive,
+    })
+}
+
+fn unknown_adapter_message(id: &str) -> String {
+    let known: Vec<&str> = adapters().iter().map(|adapter| adapter.id()).collect();
+    format!("Unknown import adapter '{}'. Use one of: auto, {}", id, known.join(", "))
+}
+
+pub async fn list_adapters(
+    State(pool): State<PgPool>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<Vec<AdapterInfo>>>, StatusCode> {
+    get_dealer_from_request(&headers, &pool).await?;
+    let list = adapters()
+        .into_iter()
+        .map(|adapter| AdapterInfo {
+            id: adapter.id().to_string(),
+            name: adapter.name().to_string(),
+        })
+        .collect();
+    Ok(Json(ApiResponse::success(list)))
+}
+
+pub async fn list_uploads(
+    State(pool): State<PgPool>,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<ApiResponse<Vec<Upload>>>, StatusCode> {
+    let dealer = get_dealer_from_request(&headers, &pool).await?;
+    let parent_id = params.get("parent_id").and_then(|s| s.parse::<i32>().ok());
+
+    let mut query = QueryBuilder::<Postgres>::new(
+        "SELECT u.id, u.dealer_id, u.filename, u.file_path, u.uploaded_at, u.status, u.row_count, u.processed_count, u.error_message, u.all_or_nothing, u.new_count, u.updated_count, u.unchanged_count, u.sheet_name, u.phase, u.rows_failed, u.scoring_version, u.reprocessed_at, u.source, u.adapter, u.parent_id, u.child_count, u.encoding, u.delimiter, j.status AS job_status, j.mode AS job_mode, j.attempts AS job_attempts, j.run_after AS job_run_after, j.last_error AS job_last_error FROM uploads u LEFT JOIN upload_jobs j ON j.upload_id = u.id WHERE u.dealer_id = "
+    );
+    query.push_bind(dealer.id);
+    if let Some(parent_id) = parent_id {
+        query.push(" AND u.parent_id = ").push_bind(parent_id);
+    }
+    query.push(" ORDER BY u.uploaded_at DESC, u.id");
+
+    let uploads = query
+        .build_query_as::<Upload>()
+        .fetch_all(&pool)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(ApiResponse::success(uploads)))
+}
+
+pub async fn get_upload(
+    State(pool): State<PgPool>,
+    headers: HeaderMap,
+    AxumPath(upload_id): AxumPath<i32>,
+) -> Result<Json<ApiResponse<Upload>>, StatusCode> {
+    let dealer = get_dealer_from_request(&headers, &pool).await?;
+    let upload = find_dealer_upload(upload_id, dealer.id, &pool).await?;
+    Ok(Json(ApiResponse::success(upload)))
+}
 
-    let file_path_str = file_path.to_string_lossy().to_string();
+// Deletes an upload with its vehicles and leads. Refused while it or one of its
+// files is being processed; a queued upload is deleted together with its job.
+pub async fn delete_upload(
+    State(pool): State<PgPool>,
+    headers: HeaderMap,
+    AxumPath(upload_id): AxumPath<i32>,
+) -> Result<Json<ApiResponse<UploadDeletion>>, StatusCode> {
+    let dealer = get_dealer_from_request(&headers, &pool).await?;
 
-    // Create upload record
+    let mut tx = pool.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     let upload = sqlx::query_as::<_, Upload>(
-        "INSERT INTO uploads (dealer_id, filename, file_path, status) VALUES ($1, $2, $3, 'processing') RETURNING id, dealer_id, filename, file_path, uploaded_at, status, row_count, processed_count, error_message"
+        "SELECT u.id, u.dealer_id, u.filename, u.file_path, u.uploaded_at, u.status, u.row_count, u.processed_count, u.error_message, u.all_or_nothing, u.new_count, u.updated_count, u.unchanged_count, u.sheet_name, u.phase, u.rows_failed, u.scoring_version, u.reprocessed_at, u.source, u.adapter, u.parent_id, u.child_count, u.encoding, u.delimiter, j.status AS job_status, j.mode AS job_mode, j.attempts AS job_attempts, j.run_after AS job_run_after, j.last_error AS job_last_error FROM uploads u LEFT JOIN upload_jobs j ON j.upload_id = u.id WHERE u.id = $1 AND u.dealer_id = $2 FOR UPDATE OF u"
     )
+    .bind(upload_id)
     .bind(dealer.id)
-    .bind(&filename)
-    .bind(&file_path_str)
-    .fetch_one(&pool)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    // An archive's upload is deleted together with the files extracted from it
+    let children: Vec<(i32, String, String)> =
+        sqlx::query_as("SELECT id, filename, file_path FROM uploads WHERE parent_id = $1 ORDER BY id FOR UPDATE")
+            .bind(upload_id)
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut upload_ids: Vec<i32> = children.iter().map(|(id, _, _)| *id).collect();
+    upload_ids.push(upload_id);
+
+    // Locking the jobs keeps workers from claiming them (they skip locked jobs)
+    let job_statuses: Vec<String> = sqlx::query_scalar("SELECT status FROM upload_jobs WHERE upload_id = ANY($1) FOR UPDATE")
+        .bind(&upload_ids)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    // An archive still being extracted, or an upload still being imported without a
+    // job we hold queued, is in use too (an archive's files are checked themselves)
+    let in_progress: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM uploads u WHERE u.id = ANY($1) AND (u.status = 'extracting' \
+         OR (u.status = 'processing' AND u.child_count = 0 \
+             AND NOT EXISTS (SELECT 1 FROM upload_jobs j WHERE j.upload_id = u.id AND j.status = 'queued')))"
+    )
+    .bind(&upload_ids)
+    .fetch_one(&mut *tx)
     .await
     .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if in_progress > 0 || job_statuses.iter().any(|status| status == "running") {
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            error: Some("Upload is still being processed; try again when it has finished".to_string()),
+        }));
+    }
 
-    // Process file asynchronously
-    let pool_clone = pool.clone();
-    let upload_id = upload.id;
-    tokio::spawn(async move {
-        if let Err(e) = process_upload_file(upload_id, &file_path_str, dealer.id, &pool_clone).await {
-            eprintln!("Error processing upload {}: {}", upload_id, e);
-            let _ = sqlx::query("UPDATE uploads SET status = 'error', error_message = $1 WHERE id = $2")
-                .bind(e.to_string())
-                .bind(upload_id)
-                .execute(&pool_clone)
-                .await;
-        }
+    let mut vehicles_deleted = 0;
+    let mut vehicles_kept = 0;
+    for (child_id, child_filename, child_file_path) in &children {
+        let removal = remove_upload(&mut tx, *child_id, dealer.id)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        let details = serde_json::json!({
+            "filename": child_filename,
+            "file_path": child_file_path,
+            "parent_id": upload_id,
+            "vehicles_deleted": removal.vehicles_deleted,
+            "vehicles_kept": removal.vehicles_kept,
+        });
+        record_audit(&mut tx, &dealer, "upload.deleted", "upload", *child_id, details)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        vehicles_deleted += removal.vehicles_deleted;
+        vehicles_kept += removal.vehicles_kept;
+    }
+
+    let removal = remove_upload(&mut tx, upload_id, dealer.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    vehicles_deleted += removal.vehicles_deleted;
+    vehicles_kept += removal.vehicles_kept;
+    let details = serde_json::json!({
+        "filename": upload.filename,
+        "file_path": upload.file_path,
+        "uploaded_at": upload.uploaded_at,
+        "status": upload.status,
+        "row_count": upload.row_count,
+        "parent_id": upload.parent_id,
+        "child_uploads_deleted": children.len(),
+        "vehicles_deleted": vehicles_deleted,
+        "vehicles_kept": vehicles_kept,
     });
+    record_audit(&mut tx, &dealer, "upload.deleted", "upload", upload_id, details)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if let Some(parent_id) = upload.parent_id {
+        refresh_archive_upload(&mut tx, parent_id)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
 
-    Ok(Json(ApiResponse::success(upload)))
+    // Uploads stored before per-dealer storage could share a file with a same-named upload
+    let mut file_paths: Vec<String> = children.into_iter().map(|(_, _, path)| path).collect();
+    file_paths.push(upload.file_path.clone());
+    let shared: Vec<String> = sqlx::query_scalar("SELECT DISTINCT file_path FROM uploads WHERE file_path = ANY($1)")
+        .bind(&file_paths)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut file_removed = false;
+    for path in file_paths.iter().filter(|path| !shared.contains(path)) {
+        let removed = tokio::fs::remove_file(path).await.is_ok();
+        if *path == upload.file_path {
+            file_removed = removed;
+        }
+    }
+
+    Ok(Json(ApiResponse::success(UploadDeletion {
+        upload_id,
+        filename: upload.filename,
+        child_uploads_deleted: upload_ids.len() as i64 - 1,
+        vehicles_deleted,
+        vehicles_kept,
+        file_removed,
+    })))
 }
 
-async fn process_upload_file(
-    upload_id: i32,
-    file_path: &str,
-    dealer_id: i32,
-    pool: &PgPool,
-) -> anyhow::Result<()> {
-    let mut reader = ReaderBuilder::new()
-        .has_headers(true)
-        .from_path(file_path)?;
-
-    let mut row_count = 0;
-    let mut processed_count = 0;
-
-    // Read CSV and process each row
-    for result in reader.records() {
-        let record = result?;
-        row_count += 1;
-
-        // Parse vehicle data from CSV
-        // Expected columns: vin, warranty_exp_date, customer_name, customer_phone, customer_email, customer_zip, last_service_date
-        if record.len() >= 4 {
-            let vin = record.get(0).unwrap_or("").to_string();
-            let warranty_exp_date_str = record.get(1).unwrap_or("");
-            let customer_name = record.get(2).unwrap_or("").to_string();
-            let customer_phone = record.get(3).unwrap_or("").to_string();
-            let customer_email = record.get(4).filter(|s| !s.is_empty()).map(|s| s.to_string());
-            let customer_zip = record.get(5).filter(|s| !s.is_empty()).map(|s| s.to_string());
-            let last_service_date_str = record.get(6).unwrap_or("");
-
-            let warranty_exp_date = warranty_exp_date_str
-                .parse::<NaiveDate>()
-                .ok();
-            let last_service_date = last_service_date_str
-                .parse::<NaiveDate>()
-                .ok();
-
-            // Insert vehicle
-            let vehicle = sqlx::query_as::<_, Vehicle>(
-                "INSERT INTO vehicles (upload_id, dealer_id, vin, warranty_exp_date, customer_name, customer_phone, customer_email, customer_zip, last_service_date) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) RETURNING id, upload_id, dealer_id, vin, warranty_exp_date, customer_name, customer_phone, customer_email, customer_zip, last_service_date, created_at"
-            )
-            .bind(upload_id)
-            .bind(dealer_id)
-            .bind(&vin)
-            .bind(&warranty_exp_date)
-            .bind(&customer_name)
-            .bind(&customer_phone)
-            .bind(&customer_email)
-            .bind(&customer_zip)
-            .bind(&last_service_date)
-            .fetch_one(pool)
-            .await?;
-
-            // Calculate scores
-            let scores = calculate_lead_scores(&vehicle);
-
-            // Insert scored lead
-            sqlx::query(
-                "INSERT INTO scored_leads (vehicle_id, upload_id, urgency_score, stressor_score, warranty_score, susceptibility_score, telematic_score, has_telematic, stressor_type, why_now, call_by_date, suggested_script) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)"
-            )
-            .bind(vehicle.id)
-            .bind(upload_id)
-            .bind(scores.urgency_score)
-            .bind(scores.stressor_score)
-            .bind(scores.warranty_score)
-            .bind(scores.susceptibility_score)
-            .bind(scores.telematic_score)
-            .bind(scores.has_telematic)
-            .bind(&scores.stressor_type)
-            .bind(&scores.why_now)
-            .bind(scores.call_by_date)
-            .bind(&scores.suggested_script)
-            .execute(pool)
-            .await?;
-
-            processed_count += 1;
+// Queues an existing upload to run again: `mode=reparse` (the default) reads the
+// stored file again, `mode=rescore` only rescores the vehicles already imported.
+pub async fn reprocess_upload(
+    State(pool): State<PgPool>,
+    headers: HeaderMap,
+    AxumPath(upload_id): AxumPath<i32>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<ApiResponse<Upload>>, StatusCode> {
+    let dealer = get_dealer_from_request(&headers, &pool).await?;
+    let mode = match params.get("mode").map(|s| s.as_str()).unwrap_or("reparse") {
+        "reparse" => JobMode::Reparse,
+        "rescore" => JobMode::Rescore,
+        _ => {
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                error: Some("mode must be 'reparse' or 'rescore'".to_string()),
+            }))
         }
+    };
+
+    let mut tx = pool.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let (file_path, status, child_count): (String, String, i32) =
+        sqlx::query_as("SELECT file_path, status, child_count FROM uploads WHERE id = $1 AND dealer_id = $2 FOR UPDATE")
+            .bind(upload_id)
+            .bind(dealer.id)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            .ok_or(StatusCode::NOT_FOUND)?;
+    let job_status: Option<String> = sqlx::query_scalar("SELECT status FROM upload_jobs WHERE upload_id = $1 FOR UPDATE")
+        .bind(upload_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let refusal = if child_count > 0 || status == "extracting" {
+        Some("This is an archive; reprocess the files extracted from it (GET /api/uploads?parent_id=) instead")
+    } else if matches!(job_status.as_deref(), Some("queued") | Some("running")) {
+        Some("Upload is already queued or processing")
+    } else if mode == JobMode::Reparse && !tokio::fs::try_exists(&file_path).await.unwrap_or(false) {
+        Some("The stored file is no longer available; rescore it or upload the file again")
+    } else {
+        None
+    };
+    if let Some(message) = refusal {
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            error: Some(message.to_string()),
+        }));
     }
 
-    // Update upload status
-    sqlx::query("UPDATE uploads SET status = 'completed', row_count = $1, processed_count = $2 WHERE id = $3")
-        .bind(row_count)
-        .bind(processed_count)
+    sqlx::query("UPDATE uploads SET status = 'processing', phase = 'queued', error_message = NULL WHERE id = $1")
         .bind(upload_id)
-        .execute(pool)
-        .await?;
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    enqueue_upload(&mut tx, upload_id, mode)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    refresh_parent_upload(&mut tx, upload_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    Ok(())
+    let upload = find_dealer_upload(upload_id, dealer.id, &pool).await?;
+    Ok(Json(ApiResponse::success(upload)))
 }
 
-pub async fn list_uploads(
+// Streams progress as Server-Sent Events until the upload completes or fails.
+// Browsers' EventSource can't set headers, so the token may also be passed as `?token=`.
+pub async fn upload_progress(
     State(pool): State<PgPool>,
     headers: HeaderMap,
-) -> Result<Json<ApiResponse<Vec<Upload>>>, StatusCode> {
+    AxumPath(upload_id): AxumPath<i32>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, StatusCode> {
+    let mut headers = headers;
+    if !headers.contains_key("Authorization") {
+        if let Some(value) = params
+            .get("token")
+            .and_then(|token| format!("Bearer {}", token).parse().ok())
+        {
+            headers.insert(header::AUTHORIZATION, value);
+        }
+    }
+    let dealer = get_dealer_from_request(&headers, &pool).await?;
+    find_dealer_upload(upload_id, dealer.id, &pool).await?;
+
+    let stream = async_stream::stream! {
+        let mut last: Option<UploadProgress> = None;
+        loop {
+            let upload = match find_dealer_upload(upload_id, dealer.id, &pool).await {
+                Ok(upload) => upload,
+                Err(_) => {
+                    yield Ok(Event::default().event("error").data("Upload is no longer available"));
+                    break;
+                }
+            };
+            let progress = UploadProgress {
+                upload_id: upload.id,
+                status: upload.status.clone(),
+                phase: upload.phase.clone(),
+                rows_read: upload.row_count,
+                rows_processed: upload.processed_count,
+                rows_failed: upload.rows_failed,
+                error_message: upload.error_message.clone(),
+            };
+
+            let event_name = match upload.status.as_str() {
+                // An archive where only some files failed still finished
+                "completed" | "partial" => Some("completed"),
+                "error" => Some("error"),
+                _ => None,
+            };
+            if let Some(name) = event_name {
+                if let Ok(event) = Event::default().event(name).json_data(&progress) {
+                    yield Ok(event);
+                }
+                break;
+            }
+            if last.as_ref() != Some(&progress) {
+                if let Ok(event) = Event::default().event("progress").json_data(&progress) {
+                    yield Ok(event);
+                }
+                last = Some(progress);
+            }
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+    };
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+pub async fn list_upload_errors(
+    State(pool): State<PgPool>,
+    headers: HeaderMap,
+    AxumPath(upload_id): AxumPath<i32>,
+) -> Result<Json<ApiResponse<Vec<UploadRowError>>>, StatusCode> {
     let dealer = get_dealer_from_request(&headers, &pool).await?;
-    let uploads = sqlx::query_as::<_, Upload>(
-        "SELECT id, dealer_id, filename, file_path, uploaded_at, status, row_count, processed_count, error_message FROM uploads WHERE dealer_id = $1 ORDER BY uploaded_at DESC"
+    find_dealer_upload(upload_id, dealer.id, &pool).await?;
+
+    let errors = sqlx::query_as::<_, UploadRowError>(
+        "SELECT id, upload_id, row_number, column_name, raw_value, reason, rejected, raw_row, created_at FROM upload_row_errors WHERE upload_id = $1 ORDER BY row_number, id"
     )
-    .bind(dealer.id)
+    .bind(upload_id)
     .fetch_all(&pool)
     .await
     .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    Ok(Json(ApiResponse::success(uploads)))
+    Ok(Json(ApiResponse::success(errors)))
 }
 
-pub async fn get_upload(
+// Returns the failed rows as they appeared in the source file, with an extra
+// `error_reason` column, so the file can be corrected and uploaded again.
+pub async fn download_upload_errors(
     State(pool): State<PgPool>,
     headers: HeaderMap,
     AxumPath(upload_id): AxumPath<i32>,
-) -> Result<Json<ApiResponse<Upload>>, StatusCode> {
+) -> Result<impl IntoResponse, StatusCode> {
     let dealer = get_dealer_from_request(&headers, &pool).await?;
-    let upload = sqlx::query_as::<_, Upload>(
-        "SELECT id, dealer_id, filename, file_path, uploaded_at, status, row_count, processed_count, error_message FROM uploads WHERE id = $1 AND dealer_id = $2"
+    let upload = find_dealer_upload(upload_id, dealer.id, &pool).await?;
+
+    let source_headers: Option<String> = sqlx::query_scalar("SELECT source_headers FROM uploads WHERE id = $1")
+        .bind(upload_id)
+        .fetch_one(&pool)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let errors = sqlx::query_as::<_, UploadRowError>(
+        "SELECT id, upload_id, row_number, column_name, raw_value, reason, rejected, raw_row, created_at FROM upload_row_errors WHERE upload_id = $1 ORDER BY row_number, id"
     )
     .bind(upload_id)
-    .bind(dealer.id)
-    .fetch_optional(&pool)
+    .fetch_all(&pool)
     .await
     .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    let upload = upload.ok_or(StatusCode::NOT_FOUND)?;
-    Ok(Json(ApiResponse::success(upload)))
+    let mut writer = WriterBuilder::new().flexible(true).from_writer(Vec::new());
+    let mut header_record = source_headers
+        .as_deref()
+        .map(decode_csv_record)
+        .unwrap_or_default();
+    header_record.push_field("error_reason");
+    writer.write_record(&header_record).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    // One output row per failed source row, with every reason for that row joined together
+    let mut i = 0;
+    while i < errors.len() {
+        let row_number = errors[i].row_number;
+        let mut reasons = Vec::new();
+        let mut record = errors[i].raw_row.as_deref().map(decode_csv_record).unwrap_or_default();
+        while i < errors.len() && errors[i].row_number == row_number {
+            let e = &errors[i];
+            match &e.column_name {
+                Some(column) => reasons.push(format!("{}: {}", column, e.reason)),
+                None => reasons.push(e.reason.clone()),
+            }
+            i += 1;
+        }
+        record.push_field(&reasons.join("; "));
+        writer.write_record(&record).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+
+    let body = writer.into_inner().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let stem = StdPath::new(&upload.filename)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| format!("upload-{}", upload.id));
+    let disposition = format!("attachment; filename=\"{}-errors.csv\"", stem.replace('"', ""));
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        body,
+    ))
+}
+
+async fn find_dealer_upload(upload_id: i32, dealer_id: i32, pool: &PgPool) -> Result<Upload, StatusCode> {
+    sqlx::query_as::<_, Upload>(
+        "SELECT u.id, u.dealer_id, u.filename, u.file_path, u.uploaded_at, u.status, u.row_count, u.processed_count, u.error_message, u.all_or_nothing, u.new_count, u.updated_count, u.unchanged_count, u.sheet_name, u.phase, u.rows_failed, u.scoring_version, u.reprocessed_at, u.source, u.adapter, u.parent_id, u.child_count, u.encoding, u.delimiter, j.status AS job_status, j.mode AS job_mode, j.attempts AS job_attempts, j.run_after AS job_run_after, j.last_error AS job_last_error FROM uploads u LEFT JOIN upload_jobs j ON j.upload_id = u.id WHERE u.id = $1 AND u.dealer_id = $2"
+    )
+    .bind(upload_id)
+    .bind(dealer_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .ok_or(StatusCode::NOT_FOUND)
+}
+
+fn decode_csv_record(line: &str) -> StringRecord {
+    ReaderBuilder::new()
+        .has_headers(false)
+        .from_reader(line.as_bytes())
+        .records()
+        .next()
+        .and_then(|r| r.ok())
+        .unwrap_or_default()
 }
 
 // Vehicle handlers
@@ -318,26 +917,138 @@ pub async fn list_vehicles(
 ) -> Result<Json<ApiResponse<Vec<Vehicle>>>, StatusCode> {
     let dealer = get_dealer_from_request(&headers, &pool).await?;
     let upload_id = params.get("upload_id").and_then(|s| s.parse::<i32>().ok());
-    
-    let vehicles = if let Some(upload_id) = upload_id {
-        sqlx::query_as::<_, Vehicle>(
-            "SELECT id, upload_id, dealer_id, vin, warranty_exp_date, customer_name, customer_phone, customer_email, customer_zip, last_service_date, created_at FROM vehicles WHERE dealer_id = $1 AND upload_id = $2 ORDER BY created_at DESC"
-        )
-        .bind(dealer.id)
-        .bind(upload_id)
+    let manufacturer = params.get("manufacturer").filter(|s| !s.is_empty());
+    let model_year = params.get("model_year").and_then(|s| s.parse::<i32>().ok());
+
+    let mut query = QueryBuilder::<Postgres>::new(
+        "SELECT id, upload_id, dealer_id, vin, warranty_exp_date, customer_name, customer_phone, customer_email, customer_zip, last_service_date, created_at, wmi, manufacturer, model_year, plant_code, customer_phone_ext, contact_status FROM vehicles WHERE dealer_id = "
+    );
+    query.push_bind(dealer.id);
+    if let Some(upload_id) = upload_id {
+        query
+            .push(" AND id IN (SELECT vehicle_id FROM vehicle_uploads WHERE upload_id = ")
+            .push_bind(upload_id)
+            .push(" OR upload_id IN (SELECT id FROM uploads WHERE parent_id = ")
+            .push_bind(upload_id)
+            .push("))");
+    }
+    if let Some(manufacturer) = manufacturer {
+        query.push(" AND manufacturer ILIKE ").push_bind(manufacturer);
+    }
+    if let Some(model_year) = model_year {
+        query.push(" AND model_year = ").push_bind(model_year);
+    }
+    query.push(" ORDER BY created_at DESC");
+    if upload_id.is_none() {
+        query.push(" LIMIT 100");
+    }
+
+    let vehicles = query
+        .build_query_as::<Vehicle>()
         .fetch_all(&pool)
         .await
-    } else {
-        sqlx::query_as::<_, Vehicle>(
-            "SELECT id, upload_id, dealer_id, vin, warranty_exp_date, customer_name, customer_phone, customer_email, customer_zip, last_service_date, created_at FROM vehicles WHERE dealer_id = $1 ORDER BY created_at DESC LIMIT 100"
-        )
-        .bind(dealer.id)
-        .fetch_all(&pool)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(ApiResponse::success(vehicles)))
+}
+
+// Queues vehicles pushed as a JSON array or as NDJSON (one object per line) as an
+// upload, imported by the job queue through the same validation and scoring as a
+// file upload. Each record is validated up front so its result carries the errors
+// its import will record. Answers `202 Accepted` once the upload is queued.
+pub async fn create_vehicle_batch(
+    State(pool): State<PgPool>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<(StatusCode, Json<ApiResponse<VehicleBatchResult>>), StatusCode> {
+    let dealer = get_dealer_from_request(&headers, &pool).await?;
+
+    let (records, filename) = match parse_vehicle_batch(&body) {
+        Ok(parsed) if !parsed.0.is_empty() => parsed,
+        Ok(_) => return Ok(batch_error("The batch contains no records".to_string())),
+        Err(message) => return Ok(batch_error(message)),
+    };
+
+    let dir = dealer_upload_dir(dealer.id);
+    tokio::fs::create_dir_all(&dir)
         .await
-    }
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let file_path = dir.join(generated_file_name("batch.csv"));
+    let settings = ImportSettings::for_dealer(&dealer);
+    let batch_path = file_path.clone();
+    let results = tokio::task::spawn_blocking(move || {
+        let readable: Vec<&VehicleInput> = records.iter().filter_map(|r| r.as_ref().ok()).collect();
+        write_batch_file(&batch_path, &readable)?;
+        validate_batch_records(&records, settings)
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
     .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    Ok(Json(ApiResponse::success(vehicles)))
+    let new_upload = NewUpload {
+        dealer_id: dealer.id,
+        filename: filename.to_string(),
+        file_path,
+        all_or_nothing: false,
+        sheet_name: None,
+        adapter: Some(GENERIC_ADAPTER.to_string()),
+        source: "api",
+        watch_path: None,
+    };
+    let upload_id = match create_upload(&pool, new_upload).await {
+        Ok(id) => id,
+        Err(CreateUploadError::Rejected(message)) => return Ok(batch_error(message)),
+        Err(CreateUploadError::Failed(_)) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+    };
+
+    let upload = find_dealer_upload(upload_id, dealer.id, &pool).await?;
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(ApiResponse::success(VehicleBatchResult {
+            upload,
+            records: results,
+        })),
+    ))
+}
+
+fn batch_error(message: String) -> (StatusCode, Json<ApiResponse<VehicleBatchResult>>) {
+    (
+        StatusCode::OK,
+        Json(ApiResponse {
+            success: false,
+            data: None,
+            error: Some(message),
+        }),
+    )
+}
+
+// A body starting with `[` is a JSON array; anything else is read as NDJSON.
+// Records that aren't valid `VehicleInput` objects are kept as errors so each
+// result still lines up with its position in the request.
+type BatchRecords = Vec<Result<VehicleInput, String>>;
+
+fn parse_vehicle_batch(body: &[u8]) -> Result<(BatchRecords, &'static str), String> {
+    fn to_input(value: serde_json::Value) -> Result<VehicleInput, String> {
+        serde_json::from_value(value).map_err(|e| format!("Invalid record: {}", e))
+    }
+
+    let text = std::str::from_utf8(body).map_err(|_| "Request body must be UTF-8".to_string())?;
+    if text.trim_start().starts_with('[') {
+        let values: Vec<serde_json::Value> =
+            serde_json::from_str(text).map_err(|e| format!("Invalid JSON array: {}", e))?;
+        return Ok((values.into_iter().map(to_input).collect(), "api-batch.json"));
+    }
+
+    let records = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            serde_json::from_str::<serde_json::Value>(line)
+                .map_err(|e| format!("Invalid JSON: {}", e))
+                .and_then(to_input)
+        })
+        .collect();
+    Ok((records, "api-batch.ndjson"))
 }
 
 pub async fn get_vehicle(
@@ -347,7 +1058,7 @@ pub async fn get_vehicle(
 ) -> Result<Json<ApiResponse<Vehicle>>, StatusCode> {
     let dealer = get_dealer_from_request(&headers, &pool).await?;
     let vehicle = sqlx::query_as::<_, Vehicle>(
-        "SELECT id, upload_id, dealer_id, vin, warranty_exp_date, customer_name, customer_phone, customer_email, customer_zip, last_service_date, created_at FROM vehicles WHERE id = $1 AND dealer_id = $2"
+        "SELECT id, upload_id, dealer_id, vin, warranty_exp_date, customer_name, customer_phone, customer_email, customer_zip, last_service_date, created_at, wmi, manufacturer, model_year, plant_code, customer_phone_ext, contact_status FROM vehicles WHERE id = $1 AND dealer_id = $2"
     )
     .bind(vehicle_id)
     .bind(dealer.id)
@@ -359,6 +1070,74 @@ pub async fn get_vehicle(
     Ok(Json(ApiResponse::success(vehicle)))
 }
 
+// Stores connected-vehicle readings sent as a JSON array or as CSV with a header
+// row, then rescores the leads of the vehicles they are for.
+pub async fn ingest_telematics(
+    State(pool): State<PgPool>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<ApiResponse<TelematicsIngestResult>>, StatusCode> {
+    let dealer = get_dealer_from_request(&headers, &pool).await?;
+    let records = match parse_readings(&body) {
+        Ok(records) if !records.is_empty() => records,
+        Ok(_) => return Ok(telematics_error("The request contains no readings".to_string())),
+        Err(message) => return Ok(telematics_error(message)),
+    };
+
+    let mut readings = Vec::new();
+    let mut results = Vec::new();
+    for (index, record) in records.iter().enumerate() {
+        let outcome = record.as_ref().map_err(|e| vec![e.clone()]).and_then(validate_reading);
+        let vin = match (record, &outcome) {
+            (_, Ok(reading)) => Some(reading.vin.clone()),
+            (Ok(raw), Err(_)) => raw.vin.clone(),
+            (Err(_), Err(_)) => None,
+        };
+        let (status, errors) = match outcome {
+            Ok(reading) => {
+                readings.push(reading);
+                ("stored", Vec::new())
+            }
+            Err(errors) => ("rejected", errors),
+        };
+        results.push(TelematicsRecordResult {
+            index,
+            vin,
+            status: status.to_string(),
+            errors,
+        });
+    }
+
+    let mut leads_rescored = 0;
+    if !readings.is_empty() {
+        let mut conn = pool.acquire().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        store_readings(&mut conn, dealer.id, &readings)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        let mut vins: Vec<String> = readings.iter().map(|r| r.vin.clone()).collect();
+        vins.sort();
+        vins.dedup();
+        leads_rescored = rescore_vehicles(&pool, dealer.id, &vins)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+
+    Ok(Json(ApiResponse::success(TelematicsIngestResult {
+        stored: readings.len() as i32,
+        rejected: (results.len() - readings.len()) as i32,
+        leads_rescored: leads_rescored as i32,
+        records: results,
+    })))
+}
+
+fn telematics_error(message: String) -> Json<ApiResponse<TelematicsIngestResult>> {
+    Json(ApiResponse {
+        success: false,
+        data: None,
+        error: Some(message),
+    })
+}
+
 // Scored leads handlers
 pub async fn list_scored_leads(
     State(pool): State<PgPool>,
@@ -367,16 +1146,60 @@ pub async fn list_scored_leads(
 ) -> Result<Json<ApiResponse<Vec<ScoredLeadWithVehicle>>>, StatusCode> {
     let dealer = get_dealer_from_request(&headers, &pool).await?;
     let upload_id = params.get("upload_id").and_then(|s| s.parse::<i32>().ok());
+    let model_id = params.get("model_id").and_then(|s| s.parse::<i32>().ok());
     let min_score = params.get("min_score").and_then(|s| s.parse::<f32>().ok());
     let limit = params.get("limit").and_then(|s| s.parse::<i32>().ok()).unwrap_or(100);
+    let as_of = match params.get("as_of") {
+        Some(value) => match NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+            Ok(date) => Some(date),
+            Err(_) => {
+                return Ok(Json(ApiResponse {
+                    success: false,
+                    data: None,
+                    error: Some("as_of must be a date in YYYY-MM-DD format".to_string()),
+                }))
+            }
+        },
+        None => None,
+    };
 
-    let mut query = "SELECT sl.id, sl.vehicle_id, sl.upload_id, sl.urgency_score, sl.stressor_score, sl.warranty_score, sl.susceptibility_score, sl.telematic_score, sl.has_telematic, sl.stressor_type, sl.why_now, sl.call_by_date, sl.suggested_script, sl.created_at, v.id, v.upload_id, v.dealer_id, v.vin, v.warranty_exp_date, v.customer_name, v.customer_phone, v.customer_email, v.customer_zip, v.last_service_date, v.created_at FROM scored_leads sl JOIN vehicles v ON sl.vehicle_id = v.id WHERE v.dealer_id = $1".to_string();
+    let mut query = "SELECT sl.id, sl.vehicle_id, sl.upload_id, sl.urgency_score, sl.stressor_score, sl.warranty_score, sl.susceptibility_score, sl.telematic_score, sl.has_telematic, sl.stressor_type, sl.why_now, sl.call_by_date, sl.suggested_script, sl.created_at, v.id, v.upload_id, v.dealer_id, v.vin, v.warranty_exp_date, v.customer_name, v.customer_phone, v.customer_email, v.customer_zip, v.last_service_date, v.created_at, v.wmi, v.manufacturer, v.model_year, v.plant_code, v.customer_phone_ext, v.contact_status, sl.scoring_version, sl.model_id, sl.inputs, sl.breakdown FROM scored_leads sl JOIN vehicles v ON sl.vehicle_id = v.id WHERE v.dealer_id = $1".to_string();
 
     let mut bind_count = 1;
     if upload_id.is_some() {
         bind_count += 1;
-        query.push_str(&format!(" AND sl.upload_id = ${}", bind_count));
+        // An archive's upload covers the leads of the files extracted from it
+        query.push_str(&format!(
+            " AND (sl.upload_id = ${0} OR sl.upload_id IN (SELECT id FROM uploads WHERE parent_id = ${0}))",
+            bind_count
+        ));
+    }
+    if model_id.is_some() {
+        bind_count += 1;
+        query.push_str(&format!(" AND sl.model_id = ${}", bind_count));
+    }
+
+    if let Some(as_of) = as_of {
+        let scorer = LeadScorer::for_dealer(&pool, dealer.id, as_of)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        let mut query_builder = sqlx::query(&query).bind(dealer.id);
+        if let Some(upload_id_val) = upload_id {
+            query_builder = query_builder.bind(upload_id_val);
+        }
+        if let Some(model_id) = model_id {
+            query_builder = query_builder.bind(model_id);
+        }
+        let mut conn = pool.acquire().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        let readings = recent_readings(&mut conn, dealer.id, None, as_of)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        let leads = project_scored_leads(query_builder.fetch(&mut *conn), &scorer, &readings, min_score, limit)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        return Ok(Json(ApiResponse::success(leads)));
     }
+
     if min_score.is_some() {
         bind_count += 1;
         query.push_str(&format!(" AND sl.urgency_score >= ${}", bind_count));
@@ -388,6 +1211,9 @@ pub async fn list_scored_leads(
     if let Some(upload_id_val) = upload_id {
         query_builder = query_builder.bind(upload_id_val);
     }
+    if let Some(model_id) = model_id {
+        query_builder = query_builder.bind(model_id);
+    }
     if let Some(min_score) = min_score {
         query_builder = query_builder.bind(min_score);
     }
@@ -398,60 +1224,62 @@ pub async fn list_scored_leads(
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
+    Ok(Json(ApiResponse::success(rows.iter().map(scored_lead_from_row).collect())))
+}
+
+// Rescores each lead's vehicle as of the scorer's date without storing anything,
+// keeping the `limit` most urgent. Leads are streamed so only those kept are held in memory.
+async fn project_scored_leads(
+    mut rows: impl Stream<Item = Result<PgRow, sqlx::Error>> + Unpin,
+    scorer: &LeadScorer,
+    readings: &HashMap<String, TelematicsReading>,
+    min_score: Option<f32>,
+    limit: i32,
+) -> Result<Vec<ScoredLeadWithVehicle>, sqlx::Error> {
+    let limit = limit.max(0) as usize;
+    let by_urgency = |a: &ScoredLeadWithVehicle, b: &ScoredLeadWithVehicle| {
+        b.lead.urgency_score.total_cmp(&a.lead.urgency_score)
+    };
+
     let mut leads = Vec::new();
-    for row in rows {
-        let lead = ScoredLead {
-            id: row.get(0),
-            vehicle_id: row.get(1),
-            upload_id: row.get(2),
-            urgency_score: row.get(3),
-            stressor_score: row.get(4),
-            warranty_score: row.get(5),
-            susceptibility_score: row.get(6),
-            telematic_score: row.get(7),
-            has_telematic: row.get(8),
-            stressor_type: row.get(9),
-            why_now: row.get(10),
-            call_by_date: row.get(11),
-            suggested_script: row.get(12),
-            created_at: row.get(13),
-        };
-        let vehicle = Vehicle {
-            id: row.get(14),
-            upload_id: row.get(15),
-            dealer_id: row.get(16),
-            vin: row.get(17),
-            warranty_exp_date: row.get(18),
-            customer_name: row.get(19),
-            customer_phone: row.get(20),
-            customer_email: row.get(21),
-            customer_zip: row.get(22),
-            last_service_date: row.get(23),
-            created_at: row.get(24),
-        };
+    while let Some(row) = rows.try_next().await? {
+        let ScoredLeadWithVehicle { mut lead, vehicle } = scored_lead_from_row(&row);
+        let scores = scorer.score(&vehicle, readings.get(&vehicle.vin));
+        if min_score.is_some_and(|min| scores.urgency_score < min) {
+            continue;
+        }
+        lead.urgency_score = scores.urgency_score;
+        lead.stressor_score = scores.stressor_score;
+        lead.warranty_score = scores.warranty_score;
+        lead.susceptibility_score = scores.susceptibility_score;
+        lead.telematic_score = scores.telematic_score;
+        lead.has_telematic = scores.has_telematic;
+        lead.stressor_type = scores.stressor_type;
+        lead.why_now = scores.why_now;
+        lead.call_by_date = scores.call_by_date;
+        lead.suggested_script = scores.suggested_script;
+        lead.scoring_version = Some(SCORING_VERSION.to_string());
+        // Nothing was stored, so no model scored this lead
+        lead.model_id = None;
+        lead.inputs = serde_json::to_value(&scores.inputs).ok();
+        lead.breakdown = serde_json::to_value(&scores.breakdown).ok();
+        lead.projected = true;
+        lead.as_of = Some(scorer.as_of);
         leads.push(ScoredLeadWithVehicle { lead, vehicle });
-    }
 
-    Ok(Json(ApiResponse::success(leads)))
+        if leads.len() >= limit.max(1) * 2 {
+            leads.sort_by(by_urgency);
+            leads.truncate(limit);
+        }
+    }
+    leads.sort_by(by_urgency);
+    leads.truncate(limit);
+    Ok(leads)
 }
 
-pub async fn get_scored_lead(
-    State(pool): State<PgPool>,
-    headers: HeaderMap,
-    AxumPath(lead_id): AxumPath<i32>,
-) -> Result<Json<ApiResponse<ScoredLeadWithVehicle>>, StatusCode> {
-    let dealer = get_dealer_from_request(&headers, &pool).await?;
-    let row = sqlx::query(
-        "SELECT sl.id, sl.vehicle_id, sl.upload_id, sl.urgency_score, sl.stressor_score, sl.warranty_score, sl.susceptibility_score, sl.telematic_score, sl.has_telematic, sl.stressor_type, sl.why_now, sl.call_by_date, sl.suggested_script, sl.created_at, v.id, v.upload_id, v.dealer_id, v.vin, v.warranty_exp_date, v.customer_name, v.customer_phone, v.customer_email, v.customer_zip, v.last_service_date, v.created_at FROM scored_leads sl JOIN vehicles v ON sl.vehicle_id = v.id WHERE sl.id = $1 AND v.dealer_id = $2"
-    )
-    .bind(lead_id)
-    .bind(dealer.id)
-    .fetch_optional(&pool)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let row = row.ok_or(StatusCode::NOT_FOUND)?;
-
+// Reads a row of the lead queries above, which select the lead's columns, then the
+// vehicle's, then `sl.scoring_version`, `sl.model_id`, `sl.inputs` and `sl.breakdown`.
+fn scored_lead_from_row(row: &PgRow) -> ScoredLeadWithVehicle {
     let lead = ScoredLead {
         id: row.get(0),
         vehicle_id: row.get(1),
@@ -467,21 +1295,51 @@ pub async fn get_scored_lead(
         call_by_date: row.get(11),
         suggested_script: row.get(12),
         created_at: row.get(13),
+        scoring_version: row.get(31),
+        model_id: row.get(32),
+        inputs: row.get(33),
+        breakdown: row.get(34),
+        projected: false,
+        as_of: None,
     };
     let vehicle = Vehicle {
         id: row.get(14),
         upload_id: row.get(15),
         dealer_id: row.get(16),
         vin: row.get(17),
+        wmi: row.get(25),
+        manufacturer: row.get(26),
+        model_year: row.get(27),
+        plant_code: row.get(28),
         warranty_exp_date: row.get(18),
         customer_name: row.get(19),
         customer_phone: row.get(20),
+        customer_phone_ext: row.get(29),
         customer_email: row.get(21),
         customer_zip: row.get(22),
         last_service_date: row.get(23),
+        contact_status: row.get(30),
         created_at: row.get(24),
     };
+    ScoredLeadWithVehicle { lead, vehicle }
+}
+
+pub async fn get_scored_lead(
+    State(pool): State<PgPool>,
+    headers: HeaderMap,
+    AxumPath(lead_id): AxumPath<i32>,
+) -> Result<Json<ApiResponse<ScoredLeadWithVehicle>>, StatusCode> {
+    let dealer = get_dealer_from_request(&headers, &pool).await?;
+    let row = sqlx::query(
+        "SELECT sl.id, sl.vehicle_id, sl.upload_id, sl.urgency_score, sl.stressor_score, sl.warranty_score, sl.susceptibility_score, sl.telematic_score, sl.has_telematic, sl.stressor_type, sl.why_now, sl.call_by_date, sl.suggested_script, sl.created_at, v.id, v.upload_id, v.dealer_id, v.vin, v.warranty_exp_date, v.customer_name, v.customer_phone, v.customer_email, v.customer_zip, v.last_service_date, v.created_at, v.wmi, v.manufacturer, v.model_year, v.plant_code, v.customer_phone_ext, v.contact_status, sl.scoring_version, sl.model_id, sl.inputs, sl.breakdown FROM scored_leads sl JOIN vehicles v ON sl.vehicle_id = v.id WHERE sl.id = $1 AND v.dealer_id = $2"
+    )
+    .bind(lead_id)
+    .bind(dealer.id)
+    .fetch_optional(&pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    Ok(Json(ApiResponse::success(ScoredLeadWithVehicle { lead, vehicle })))
+    let row = row.ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(ApiResponse::success(scored_lead_from_row(&row))))
 }
 
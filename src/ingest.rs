@@ -0,0 +1,556 @@
+use crate::adapters::RowMapper;
+use crate::contact::{normalize_email, normalize_phone, parse_country, ContactStatus};
+use crate::dates::{ambiguity_note, parse_date, DateOrder};
+use crate::models::{Dealer, VehicleInput};
+use phonenumber::country::Id as CountryId;
+use crate::vin::{decode_vin, DecodedVin};
+use chrono::NaiveDate;
+use csv::StringRecord;
+use std::collections::HashMap;
+
+// Lengths of the `vehicles` columns the values are stored in
+const MAX_RAW_PHONE_CHARS: usize = 50;
+const MAX_NAME_CHARS: usize = 255;
+const MAX_EMAIL_CHARS: usize = 255;
+const MAX_ZIP_CHARS: usize = 10;
+
+// Canonical upload columns and the header spellings we accept for each.
+// Headers are compared after normalization (lowercased, non-alphanumerics stripped),
+// so "Vehicle VIN", "vehicle_vin" and "VEHICLE-VIN" all match "vehiclevin".
+const VIN_ALIASES: &[&str] = &["vin", "vehiclevin", "vinnumber", "vehicleidentificationnumber", "serialnumber"];
+const WARRANTY_EXP_DATE_ALIASES: &[&str] = &[
+    "warrantyexpdate",
+    "warrantyexpiration",
+    "warrantyexpirationdate",
+    "warrantyexpires",
+    "warrantyend",
+    "warrantyenddate",
+];
+const CUSTOMER_NAME_ALIASES: &[&str] = &["customername", "custname", "name", "customer", "ownername", "fullname"];
+const CUSTOMER_PHONE_ALIASES: &[&str] = &[
+    "customerphone",
+    "custphone",
+    "phone",
+    "phonenumber",
+    "homephone",
+    "cellphone",
+    "mobilephone",
+    "primaryphone",
+];
+const CUSTOMER_EMAIL_ALIASES: &[&str] = &["customeremail", "custemail", "email", "emailaddress"];
+const CUSTOMER_ZIP_ALIASES: &[&str] = &["customerzip", "custzip", "zip", "zipcode", "postalcode"];
+const LAST_SERVICE_DATE_ALIASES: &[&str] = &[
+    "lastservicedate",
+    "lastservice",
+    "lastserviced",
+    "lastrodate",
+    "lastvisitdate",
+];
+
+/// Positions of the known upload columns within a file's header row.
+#[derive(Debug, Clone)]
+pub struct ColumnMap {
+    pub vin: usize,
+    pub warranty_exp_date: Option<usize>,
+    pub customer_name: usize,
+    pub customer_phone: usize,
+    pub customer_email: Option<usize>,
+    pub customer_zip: Option<usize>,
+    pub last_service_date: Option<usize>,
+}
+
+impl ColumnMap {
+    /// Resolves columns by header name. Unknown columns are ignored; if any
+    /// required column is absent the error names every missing one.
+    pub fn from_headers(headers: &StringRecord) -> anyhow::Result<Self> {
+        let normalized: Vec<String> = headers.iter().map(normalize_header).collect();
+        let find = |aliases: &[&str]| {
+            aliases
+                .iter()
+                .find_map(|alias| normalized.iter().position(|h| h == alias))
+        };
+
+        let vin = find(VIN_ALIASES);
+        let customer_name = find(CUSTOMER_NAME_ALIASES);
+        let customer_phone = find(CUSTOMER_PHONE_ALIASES);
+
+        let mut missing = Vec::new();
+        if vin.is_none() {
+            missing.push("vin");
+        }
+        if customer_name.is_none() {
+            missing.push("customer_name");
+        }
+        if customer_phone.is_none() {
+            missing.push("customer_phone");
+        }
+        if !missing.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Missing required column(s): {}. Found headers: {}",
+                missing.join(", "),
+                headers.iter().collect::<Vec<_>>().join(", ")
+            ));
+        }
+
+        Ok(ColumnMap {
+            vin: vin.unwrap(),
+            warranty_exp_date: find(WARRANTY_EXP_DATE_ALIASES),
+            customer_name: customer_name.unwrap(),
+            customer_phone: customer_phone.unwrap(),
+            customer_email: find(CUSTOMER_EMAIL_ALIASES),
+            customer_zip: find(CUSTOMER_ZIP_ALIASES),
+            last_service_date: find(LAST_SERVICE_DATE_ALIASES),
+        })
+    }
+
+    /// The header matched for each known column, in canonical column order.
+    pub fn mapping(&self, headers: &StringRecord) -> Vec<(&'static str, Option<String>)> {
+        let header = |index: Option<usize>| index.and_then(|i| headers.get(i)).map(|h| h.to_string());
+        vec![
+            ("vin", header(Some(self.vin))),
+            ("warranty_exp_date", header(self.warranty_exp_date)),
+            ("customer_name", header(Some(self.customer_name))),
+            ("customer_phone", header(Some(self.customer_phone))),
+            ("customer_email", header(self.customer_email)),
+            ("customer_zip", header(self.customer_zip)),
+            ("last_service_date", header(self.last_service_date)),
+        ]
+    }
+
+    /// Reads one data row into a `VehicleInput`, leaving all validation to `validate_input`.
+    pub fn to_input(&self, record: &StringRecord) -> VehicleInput {
+        VehicleInput {
+            vin: self.get(record, self.vin).to_string(),
+            warranty_exp_date: self.get_opt(record, self.warranty_exp_date).map(|s| s.to_string()),
+            customer_name: self.get(record, self.customer_name).to_string(),
+            customer_phone: self.get(record, self.customer_phone).to_string(),
+            customer_email: self.get_opt(record, self.customer_email).map(|s| s.to_string()),
+            customer_zip: self.get_opt(record, self.customer_zip).map(|s| s.to_string()),
+            last_service_date: self.get_opt(record, self.last_service_date).map(|s| s.to_string()),
+        }
+    }
+
+    /// Trimmed value of a required column, empty if the row is short.
+    fn get<'a>(&self, record: &'a StringRecord, index: usize) -> &'a str {
+        record.get(index).unwrap_or("").trim()
+    }
+
+    /// Trimmed value of an optional column, `None` if absent or blank.
+    fn get_opt<'a>(&self, record: &'a StringRecord, index: Option<usize>) -> Option<&'a str> {
+        index
+            .and_then(|i| record.get(i))
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+    }
+}
+
+/// Per-dealer settings that affect how row values are read.
+#[derive(Debug, Clone, Copy)]
+pub struct ImportSettings {
+    pub date_order: DateOrder,
+    pub default_country: CountryId,
+}
+
+impl ImportSettings {
+    pub fn for_dealer(dealer: &Dealer) -> Self {
+        ImportSettings {
+            date_order: DateOrder::from_setting(&dealer.date_order).unwrap_or_default(),
+            default_country: parse_country(&dealer.default_country).unwrap_or(CountryId::US),
+        }
+    }
+}
+
+/// A vehicle row that passed validation and is ready to insert.
+#[derive(Debug, Clone)]
+pub struct ValidatedVehicle {
+    pub vin_info: DecodedVin,
+    pub warranty_exp_date: Option<NaiveDate>,
+    pub customer_name: String,
+    /// E.164 when `contact_status` is `Valid`, otherwise as typed
+    pub customer_phone: String,
+    pub customer_phone_ext: Option<String>,
+    pub customer_email: Option<String>,
+    pub customer_zip: Option<String>,
+    pub last_service_date: Option<NaiveDate>,
+    pub contact_status: ContactStatus,
+}
+
+/// A problem found with a single value in an input row.
+#[derive(Debug, Clone)]
+pub struct RowIssue {
+    pub column: String,
+    pub raw_value: Option<String>,
+    pub reason: String,
+}
+
+/// Result of validating one input row. `vehicle` is `None` when the row was rejected;
+/// otherwise `issues` lists the values that were dropped or guessed while keeping the row.
+#[derive(Debug, Clone)]
+pub struct RowOutcome {
+    pub vehicle: Option<ValidatedVehicle>,
+    pub issues: Vec<RowIssue>,
+}
+
+impl RowOutcome {
+    pub fn is_rejected(&self) -> bool {
+        self.vehicle.is_none()
+    }
+}
+
+pub fn validate_input(input: &VehicleInput, settings: &ImportSettings) -> RowOutcome {
+    let mut issues = Vec::new();
+    let mut rejected = false;
+
+    let vin_info = if input.vin.trim().is_empty() {
+        None
+    } else {
+        match decode_vin(&input.vin) {
+            Ok(decoded) => Some(decoded),
+            Err(reason) => {
+                issues.push(RowIssue {
+                    column: "vin".to_string(),
+                    raw_value: Some(input.vin.clone()),
+                    reason,
+                });
+                rejected = true;
+                None
+            }
+        }
+    };
+
+    for (column, value) in [("vin", &input.vin), ("customer_name", &input.customer_name)] {
+        if value.trim().is_empty() {
+            issues.push(RowIssue {
+                column: column.to_string(),
+                raw_value: None,
+                reason: format!("{} is required", column),
+            });
+            rejected = true;
+        }
+    }
+    if input.customer_name.trim().chars().count() > MAX_NAME_CHARS {
+        issues.push(RowIssue {
+            column: "customer_name".to_string(),
+            raw_value: Some(input.customer_name.clone()),
+            reason: format!("customer_name is longer than {} characters", MAX_NAME_CHARS),
+        });
+        rejected = true;
+    }
+
+    let warranty_exp_date = parse_date_field("warranty_exp_date", input.warranty_exp_date.as_deref(), settings.date_order, &mut issues);
+    let last_service_date = parse_date_field("last_service_date", input.last_service_date.as_deref(), settings.date_order, &mut issues);
+
+    let (customer_phone, customer_phone_ext, contact_status) =
+        parse_phone_field(&input.customer_phone, settings.default_country, &mut issues);
+    let customer_email = non_blank(input.customer_email.as_deref()).and_then(|email| match normalize_email(&email) {
+        Ok(email) => Some(email),
+        Err(reason) => {
+            issues.push(RowIssue {
+                column: "customer_email".to_string(),
+                raw_value: Some(email),
+                reason,
+            });
+            None
+        }
+    });
+    let customer_email = fit_column("customer_email", customer_email, MAX_EMAIL_CHARS, &mut issues);
+    let customer_zip = fit_column("customer_zip", non_blank(input.customer_zip.as_deref()), MAX_ZIP_CHARS, &mut issues);
+
+    let vin_info = match vin_info {
+        Some(vin_info) if !rejected => vin_info,
+        _ => return RowOutcome { vehicle: None, issues },
+    };
+
+    RowOutcome {
+        vehicle: Some(ValidatedVehicle {
+            vin_info,
+            warranty_exp_date,
+            customer_name: input.customer_name.trim().to_string(),
+            customer_phone,
+            customer_phone_ext,
+            customer_email,
+            customer_zip,
+            last_service_date,
+            contact_status,
+        }),
+        issues,
+    }
+}
+
+/// Validates the data rows of one file in order, rejecting any row whose VIN
+/// already appeared earlier in the same file.
+pub struct RowValidator {
+    mapper: Box<dyn RowMapper>,
+    settings: ImportSettings,
+    seen_vins: HashMap<String, i32>,
+}
+
+impl RowValidator {
+    pub fn new(mapper: Box<dyn RowMapper>, settings: ImportSettings) -> Self {
+        RowValidator {
+            mapper,
+            settings,
+            seen_vins: HashMap::new(),
+        }
+    }
+
+    pub fn mapper(&self) -> &dyn RowMapper {
+        self.mapper.as_ref()
+    }
+
+    pub fn validate(&mut self, row_number: i32, record: &StringRecord) -> RowOutcome {
+        let mut outcome = validate_input(&self.mapper.to_input(record), &self.settings);
+        if let Some(row) = &outcome.vehicle {
+            if let Some(first_row) = self.seen_vins.get(&row.vin_info.vin) {
+                outcome.issues.push(RowIssue {
+                    column: "vin".to_string(),
+                    raw_value: Some(row.vin_info.vin.clone()),
+                    reason: format!("Duplicate VIN; already imported from row {} of this file", first_row),
+                });
+                outcome.vehicle = None;
+            } else {
+                self.seen_vins.insert(row.vin_info.vin.clone(), row_number);
+            }
+        }
+        outcome
+    }
+}
+
+fn parse_date_field(
+    column: &str,
+    value: Option<&str>,
+    date_order: DateOrder,
+    issues: &mut Vec<RowIssue>,
+) -> Option<NaiveDate> {
+    let value = value.map(str::trim).filter(|s| !s.is_empty())?;
+    match parse_date(value, date_order) {
+        Some(parsed) => {
+            if parsed.ambiguous {
+                issues.push(RowIssue {
+                    column: column.to_string(),
+                    raw_value: Some(value.to_string()),
+                    reason: ambiguity_note(&parsed, date_order),
+                });
+            }
+            Some(parsed.date)
+        }
+        None => {
+            issues.push(RowIssue {
+                column: column.to_string(),
+                raw_value: Some(value.to_string()),
+                reason: "Unrecognized date format; value ignored".to_string(),
+            });
+            None
+        }
+    }
+}
+
+// Invalid numbers are kept as typed, so they can still be corrected by hand, but
+// are cut to fit the column.
+fn parse_phone_field(
+    value: &str,
+    default_country: CountryId,
+    issues: &mut Vec<RowIssue>,
+) -> (String, Option<String>, ContactStatus) {
+    let value = value.trim();
+    if value.is_empty() {
+        issues.push(RowIssue {
+            column: "customer_phone".to_string(),
+            raw_value: None,
+            reason: "No phone number; contact status set to missing".to_string(),
+        });
+        return (String::new(), None, ContactStatus::Missing);
+    }
+    match normalize_phone(value, default_country) {
+        Ok(phone) => (phone.e164, phone.extension, ContactStatus::Valid),
+        Err(reason) => {
+            issues.push(RowIssue {
+                column: "customer_phone".to_string(),
+                raw_value: Some(value.to_string()),
+                reason,
+            });
+            (value.chars().take(MAX_RAW_PHONE_CHARS).collect(), None, ContactStatus::Invalid)
+        }
+    }
+}
+
+// Drops an optional value too long for its column, noting why.
+fn fit_column(column: &str, value: Option<String>, max_chars: usize, issues: &mut Vec<RowIssue>) -> Option<String> {
+    let value = value?;
+    if value.chars().count() <= max_chars {
+        return Some(value);
+    }
+    issues.push(RowIssue {
+        column: column.to_string(),
+        raw_value: Some(value),
+        reason: format!("{} is longer than {} characters; value ignored", column, max_chars),
+    });
+    None
+}
+
+fn non_blank(value: Option<&str>) -> Option<String> {
+    value.map(str::trim).filter(|s| !s.is_empty()).map(|s| s.to_string())
+}
+
+pub(crate) fn normalize_header(header: &str) -> String {
+    header
+        .trim_start_matches('\u{feff}')
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::{adapter_by_id, GENERIC_ADAPTER};
+
+    const VIN: &str = "1HGCM82633A004352";
+
+    fn settings() -> ImportSettings {
+        ImportSettings {
+            date_order: DateOrder::MonthFirst,
+            default_country: CountryId::US,
+        }
+    }
+
+    fn input() -> VehicleInput {
+        VehicleInput {
+            vin: VIN.to_string(),
+            warranty_exp_date: Some("2026-06-30".to_string()),
+            customer_name: "Pat Doe".to_string(),
+            customer_phone: "(415) 555-0132".to_string(),
+            customer_email: Some("Pat@Example.COM".to_string()),
+            customer_zip: Some("94107".to_string()),
+            last_service_date: Some("03/15/2025".to_string()),
+        }
+    }
+
+    // A change to a valid row and the (column, reason) of each issue it should raise
+    type Case = (fn(&mut VehicleInput), &'static [(&'static str, &'static str)]);
+
+    // Column and reason of each issue, in order
+    fn issues(outcome: &RowOutcome) -> Vec<(&str, &str)> {
+        outcome
+            .issues
+            .iter()
+            .map(|issue| (issue.column.as_str(), issue.reason.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn valid_row_is_normalized() {
+        let outcome = validate_input(&input(), &settings());
+        assert!(outcome.issues.is_empty(), "{:?}", outcome.issues);
+        let vehicle = outcome.vehicle.unwrap();
+        assert_eq!(vehicle.vin_info.vin, VIN);
+        assert_eq!(vehicle.customer_phone, "+14155550132");
+        assert_eq!(vehicle.customer_email.as_deref(), Some("Pat@example.com"));
+        assert_eq!(vehicle.warranty_exp_date, NaiveDate::from_ymd_opt(2026, 6, 30));
+        assert_eq!(vehicle.last_service_date, NaiveDate::from_ymd_opt(2025, 3, 15));
+        assert_eq!(vehicle.contact_status, ContactStatus::Valid);
+    }
+
+    #[test]
+    fn rejected_fields_are_reported_per_column() {
+        let cases: [Case; 4] = [
+            (|i| i.vin = " ".to_string(), &[("vin", "vin is required")]),
+            (|i| i.vin = "1HGCM82643A004352".to_string(), &[("vin", "VIN check digit is '4', expected '3'")]),
+            (|i| i.customer_name = String::new(), &[("customer_name", "customer_name is required")]),
+            (
+                |i| i.customer_name = "N".repeat(256),
+                &[("customer_name", "customer_name is longer than 255 characters")],
+            ),
+        ];
+        for (change, expected) in cases {
+            let mut row = input();
+            change(&mut row);
+            let outcome = validate_input(&row, &settings());
+            assert!(outcome.is_rejected(), "{:?}", expected);
+            assert_eq!(issues(&outcome), expected);
+        }
+    }
+
+    #[test]
+    fn dropped_fields_keep_the_row() {
+        let cases: [Case; 7] = [
+            (
+                |i| i.warranty_exp_date = Some("soon".to_string()),
+                &[("warranty_exp_date", "Unrecognized date format; value ignored")],
+            ),
+            (
+                |i| i.last_service_date = Some("03/04/2025".to_string()),
+                &[(
+                    "last_service_date",
+                    "Ambiguous date; read as month-first (2025-03-04) per the dealer's date order setting",
+                )],
+            ),
+            (
+                |i| i.customer_email = Some("not-an-email".to_string()),
+                &[("customer_email", "Invalid email address; value ignored")],
+            ),
+            (
+                |i| i.customer_email = Some(format!("{}@{}example.com", "e".repeat(64), format!("{}.", "d".repeat(63)).repeat(3))),
+                &[("customer_email", "customer_email is longer than 255 characters; value ignored")],
+            ),
+            (
+                |i| i.customer_zip = Some("94107-1234-56".to_string()),
+                &[("customer_zip", "customer_zip is longer than 10 characters; value ignored")],
+            ),
+            (
+                |i| i.customer_phone = "12".to_string(),
+                &[("customer_phone", "Not a phone number; kept as typed")],
+            ),
+            (
+                |i| i.customer_phone = "(000) 555-0132".to_string(),
+                &[("customer_phone", "Not a valid phone number; kept as typed")],
+            ),
+        ];
+        for (change, expected) in cases {
+            let mut row = input();
+            change(&mut row);
+            let outcome = validate_input(&row, &settings());
+            assert!(!outcome.is_rejected(), "{:?}", expected);
+            assert_eq!(issues(&outcome), expected);
+        }
+    }
+
+    #[test]
+    fn missing_phone_is_kept_as_missing() {
+        let mut row = input();
+        row.customer_phone = String::new();
+        let outcome = validate_input(&row, &settings());
+        assert_eq!(issues(&outcome), [("customer_phone", "No phone number; contact status set to missing")]);
+        assert_eq!(outcome.vehicle.unwrap().contact_status, ContactStatus::Missing);
+    }
+
+    #[test]
+    fn duplicate_vin_in_a_file_is_rejected() {
+        let headers = StringRecord::from(vec!["VIN", "Customer Name", "Phone"]);
+        let mapper = adapter_by_id(GENERIC_ADAPTER).unwrap().bind(&headers).unwrap();
+        let mut validator = RowValidator::new(mapper, settings());
+
+        let first = validator.validate(2, &StringRecord::from(vec![VIN, "Pat Doe", "4155550132"]));
+        assert!(!first.is_rejected());
+        let other = validator.validate(3, &StringRecord::from(vec!["11111111111111111", "Sam Roe", "4155550133"]));
+        assert!(!other.is_rejected());
+
+        // Matched after normalization, so case and spacing don't hide a repeat
+        let repeat = validator.validate(4, &StringRecord::from(vec![" 1hgcm82633a004352", "Pat Doe", "4155550132"]));
+        assert!(repeat.is_rejected());
+        assert_eq!(issues(&repeat), [("vin", "Duplicate VIN; already imported from row 2 of this file")]);
+    }
+
+    #[test]
+    fn headers_resolve_through_aliases() {
+        let headers = StringRecord::from(vec!["Vehicle VIN", "Owner Name", "Cell Phone", "E-mail Address", "Postal Code"]);
+        let columns = ColumnMap::from_headers(&headers).unwrap();
+        assert_eq!((columns.vin, columns.customer_name, columns.customer_phone), (0, 1, 2));
+        assert_eq!((columns.customer_email, columns.customer_zip), (Some(3), Some(4)));
+        assert_eq!(columns.warranty_exp_date, None);
+
+        let error = ColumnMap::from_headers(&StringRecord::from(vec!["VIN", "Email"])).unwrap_err();
+        assert!(error.to_string().starts_with("Missing required column(s): customer_name, customer_phone"));
+    }
+}
@@ -0,0 +1,297 @@
+use crate::pipeline::{process_upload_file, rescore_upload};
+use crate::quotas::DealerLimits;
+use crate::uploads::refresh_parent_upload;
+use sqlx::{PgConnection, PgPool};
+use std::time::Duration;
+
+// A running job whose heartbeat is older than this is assumed to belong to a
+// worker that died (e.g. a restart mid-upload) and is put back in the queue.
+const LEASE_SECONDS: i64 = 90;
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const BASE_BACKOFF_SECONDS: i64 = 30;
+const MAX_BACKOFF_SECONDS: i64 = 1800;
+const STALE_JOB_ERROR: &str = "Worker stopped while processing the upload";
+// Extraction runs in the request that created the upload, well within this
+const EXTRACTION_TIMEOUT_SECONDS: i64 = 3600;
+const STALE_EXTRACTION_ERROR: &str = "Server stopped while extracting the archive";
+
+/// What a job does with its upload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobMode {
+    /// First import of a new upload
+    Import,
+    /// Parse the stored file again and replace the upload's data in one transaction
+    Reparse,
+    /// Rescore the upload's vehicles as they are, without reading the file
+    Rescore,
+}
+
+impl JobMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobMode::Import => "import",
+            JobMode::Reparse => "reparse",
+            JobMode::Rescore => "rescore",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "import" => Some(JobMode::Import),
+            "reparse" => Some(JobMode::Reparse),
+            "rescore" => Some(JobMode::Rescore),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct ClaimedJob {
+    id: i32,
+    upload_id: i32,
+    attempts: i32,
+    max_attempts: i32,
+    mode: String,
+    file_path: String,
+    dealer_id: i32,
+}
+
+/// Queues an upload for processing. Call inside the transaction that creates or
+/// updates the upload. An upload has one job, which is reset if it already exists.
+pub async fn enqueue_upload(conn: &mut PgConnection, upload_id: i32, mode: JobMode) -> anyhow::Result<()> {
+    sqlx::query(
+        "INSERT INTO upload_jobs (upload_id, mode) VALUES ($1, $2) \
+         ON CONFLICT (upload_id) DO UPDATE SET status = 'queued', mode = EXCLUDED.mode, attempts = 0, run_after = NOW(), last_error = NULL, locked_by = NULL, updated_at = NOW()"
+    )
+    .bind(upload_id)
+    .bind(mode.as_str())
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Recovers jobs abandoned by dead workers, then starts `count` worker loops.
+pub async fn start_workers(pool: PgPool, count: usize) -> anyhow::Result<()> {
+    let recovered = requeue_stale_jobs(&pool).await?;
+    if recovered > 0 {
+        println!("Requeued {} upload job(s) left running by a previous process", recovered);
+    }
+    let abandoned = fail_stale_extractions(&pool).await?;
+    if abandoned > 0 {
+        println!("Failed {} archive upload(s) left extracting by a previous process", abandoned);
+    }
+
+    let instance = uuid::Uuid::new_v4().simple().to_string();
+    for n in 0..count {
+        let pool = pool.clone();
+        let worker_id = format!("{}-{}", &instance[..8], n);
+        tokio::spawn(async move { run_worker(pool, worker_id).await });
+    }
+    Ok(())
+}
+
+async fn run_worker(pool: PgPool, worker_id: String) {
+    loop {
+        match claim_next_job(&pool, &worker_id).await {
+            Ok(Some(job)) => run_job(&pool, job).await,
+            Ok(None) => {
+                if let Err(e) = requeue_stale_jobs(&pool).await {
+                    eprintln!("Worker {} failed to requeue stale jobs: {}", worker_id, e);
+                }
+                if let Err(e) = fail_stale_extractions(&pool).await {
+                    eprintln!("Worker {} failed to sweep stale extractions: {}", worker_id, e);
+                }
+                tokio::time::sleep(POLL_INTERVAL).await;
+            }
+            Err(e) => {
+                eprintln!("Worker {} failed to claim a job: {}", worker_id, e);
+                tokio::time::sleep(POLL_INTERVAL * 5).await;
+            }
+        }
+    }
+}
+
+// Claims are serialized with this advisory lock (dealer locks use positive dealer
+// ids) so two workers can't both take a dealer's last processing slot.
+const CLAIM_LOCK_KEY: i64 = -1;
+
+// Takes the oldest queued job whose dealer is below its concurrent job limit.
+async fn claim_next_job(pool: &PgPool, worker_id: &str) -> anyhow::Result<Option<ClaimedJob>> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("SELECT pg_advisory_xact_lock($1)")
+        .bind(CLAIM_LOCK_KEY)
+        .execute(&mut *tx)
+        .await?;
+    let job = sqlx::query_as::<_, ClaimedJob>(
+        "WITH next AS ( \
+             SELECT q.id FROM upload_jobs q \
+             JOIN uploads qu ON qu.id = q.upload_id \
+             JOIN dealers d ON d.id = qu.dealer_id \
+             WHERE q.status = 'queued' AND q.run_after <= NOW() \
+             AND ( \
+                 SELECT COUNT(*) FROM upload_jobs r JOIN uploads ru ON ru.id = r.upload_id \
+                 WHERE r.status = 'running' AND ru.dealer_id = qu.dealer_id \
+             ) < COALESCE(d.max_concurrent_jobs, $2) \
+             ORDER BY q.run_after, q.id FOR UPDATE OF q SKIP LOCKED LIMIT 1 \
+         ) \
+         UPDATE upload_jobs j SET status = 'running', attempts = j.attempts + 1, locked_by = $1, heartbeat_at = NOW(), updated_at = NOW() \
+         FROM next, uploads u \
+         WHERE j.id = next.id AND u.id = j.upload_id \
+         RETURNING j.id, j.upload_id, j.attempts, j.max_attempts, j.mode, u.file_path, u.dealer_id"
+    )
+    .bind(worker_id)
+    .bind(DealerLimits::defaults().concurrent_jobs)
+    .fetch_optional(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(job)
+}
+
+async fn run_job(pool: &PgPool, job: ClaimedJob) {
+    let heartbeat = {
+        let pool = pool.clone();
+        let job_id = job.id;
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(HEARTBEAT_INTERVAL).await;
+                let _ = sqlx::query("UPDATE upload_jobs SET heartbeat_at = NOW() WHERE id = $1 AND status = 'running'")
+                    .bind(job_id)
+                    .execute(&pool)
+                    .await;
+            }
+        })
+    };
+
+    let result = match JobMode::parse(&job.mode) {
+        Some(JobMode::Import) => process_upload_file(job.upload_id, &job.file_path, job.dealer_id, false, pool).await,
+        Some(JobMode::Reparse) => process_upload_file(job.upload_id, &job.file_path, job.dealer_id, true, pool).await,
+        Some(JobMode::Rescore) => rescore_upload(job.upload_id, job.dealer_id, pool).await,
+        None => Err(anyhow::anyhow!("Unknown job mode '{}'", job.mode)),
+    };
+    heartbeat.abort();
+
+    let outcome = match result {
+        Ok(()) => sqlx::query("UPDATE upload_jobs SET status = 'completed', last_error = NULL, locked_by = NULL, updated_at = NOW() WHERE id = $1")
+            .bind(job.id)
+            .execute(pool)
+            .await
+            .map(|_| ()),
+        Err(e) => {
+            eprintln!("Error processing upload {} (attempt {}): {}", job.upload_id, job.attempts, e);
+            // Only database failures are worth retrying; a file that can't be
+            // parsed or fails validation will fail the same way next time.
+            let max_attempts = if e.downcast_ref::<sqlx::Error>().is_some() {
+                job.max_attempts
+            } else {
+                job.attempts
+            };
+            fail_job(pool, job.id, job.upload_id, job.attempts, max_attempts, &e.to_string()).await
+        }
+    };
+    if let Err(e) = outcome {
+        eprintln!("Failed to record result of upload job {}: {}", job.id, e);
+    }
+
+    // An archive's upload reflects each of its files as they finish
+    let refreshed = match pool.acquire().await {
+        Ok(mut conn) => refresh_parent_upload(&mut conn, job.upload_id).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = refreshed {
+        eprintln!("Failed to update the archive upload of upload {}: {}", job.upload_id, e);
+    }
+}
+
+// Retries with exponential backoff until `max_attempts`, then marks the upload as errored.
+async fn fail_job(
+    pool: &PgPool,
+    job_id: i32,
+    upload_id: i32,
+    attempts: i32,
+    max_attempts: i32,
+    error: &str,
+) -> Result<(), sqlx::Error> {
+    if attempts < max_attempts {
+        let backoff = (BASE_BACKOFF_SECONDS << (attempts - 1).clamp(0, 16)).min(MAX_BACKOFF_SECONDS);
+        let mut tx = pool.begin().await?;
+        sqlx::query(
+            "UPDATE upload_jobs SET status = 'queued', run_after = NOW() + make_interval(secs => $1), last_error = $2, locked_by = NULL, updated_at = NOW() WHERE id = $3"
+        )
+        .bind(backoff as f64)
+        .bind(error)
+        .bind(job_id)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("UPDATE uploads SET phase = 'queued' WHERE id = $1")
+            .bind(upload_id)
+            .execute(&mut *tx)
+            .await?;
+        return tx.commit().await;
+    }
+
+    let mut tx = pool.begin().await?;
+    sqlx::query("UPDATE upload_jobs SET status = 'failed', last_error = $1, locked_by = NULL, updated_at = NOW() WHERE id = $2")
+        .bind(error)
+        .bind(job_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("UPDATE uploads SET status = 'error', phase = 'error', error_message = $1 WHERE id = $2")
+        .bind(error)
+        .bind(upload_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await
+}
+
+// Jobs are requeued straight away; the attempt they were on still counts towards `max_attempts`.
+async fn requeue_stale_jobs(pool: &PgPool) -> anyhow::Result<u64> {
+    let mut tx = pool.begin().await?;
+    let requeued: Vec<(i32, String)> = sqlx::query_as(
+        "UPDATE upload_jobs SET status = CASE WHEN attempts < max_attempts THEN 'queued' ELSE 'failed' END, \
+         run_after = NOW(), last_error = $1, locked_by = NULL, updated_at = NOW() \
+         WHERE status = 'running' AND heartbeat_at < NOW() - make_interval(secs => $2) \
+         RETURNING upload_id, status"
+    )
+    .bind(STALE_JOB_ERROR)
+    .bind(LEASE_SECONDS as f64)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let (failed, queued): (Vec<_>, Vec<_>) = requeued.iter().partition(|(_, status)| status == "failed");
+    let failed: Vec<i32> = failed.into_iter().map(|(upload_id, _)| *upload_id).collect();
+    let queued: Vec<i32> = queued.into_iter().map(|(upload_id, _)| *upload_id).collect();
+    if !failed.is_empty() {
+        sqlx::query("UPDATE uploads SET status = 'error', phase = 'error', error_message = $1 WHERE id = ANY($2)")
+            .bind(STALE_JOB_ERROR)
+            .bind(&failed)
+            .execute(&mut *tx)
+            .await?;
+        for upload_id in &failed {
+            refresh_parent_upload(&mut tx, *upload_id).await?;
+        }
+    }
+    if !queued.is_empty() {
+        sqlx::query("UPDATE uploads SET phase = 'queued' WHERE id = ANY($1)")
+            .bind(&queued)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+
+    Ok(requeued.len() as u64)
+}
+
+// Archive uploads are `extracting` only while the request that created them runs, so
+// one still extracting long after it was uploaded was abandoned by a stopped server.
+async fn fail_stale_extractions(pool: &PgPool) -> anyhow::Result<u64> {
+    let failed = sqlx::query(
+        "UPDATE uploads SET status = 'error', phase = 'error', error_message = $1 \
+         WHERE status = 'extracting' AND uploaded_at < NOW() - make_interval(secs => $2)"
+    )
+    .bind(STALE_EXTRACTION_ERROR)
+    .bind(EXTRACTION_TIMEOUT_SECONDS as f64)
+    .execute(pool)
+    .await?;
+    Ok(failed.rows_affected())
+}
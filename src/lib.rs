@@ -1,8 +1,23 @@
+pub mod adapters;
+pub mod archives;
 pub mod auth;
+pub mod charset;
+pub mod contact;
 pub mod handlers;
+pub mod ingest;
+pub mod jobs;
 pub mod models;
+pub mod pipeline;
+pub mod quotas;
+pub mod reader;
 pub mod scoring;
+pub mod storage;
+pub mod telematics;
+pub mod uploads;
+pub mod watch;
+pub mod dates;
 pub mod db;
+pub mod vin;
 
 pub use db::*;
 
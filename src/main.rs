@@ -1,4 +1,5 @@
 use axum::{
+    extract::DefaultBodyLimit,
     http::Method,
     routing::{get, post},
     Router,
@@ -6,6 +7,9 @@ use axum::{
 use stressor_leads::{
     db::{create_pool, run_migrations},
     handlers::*,
+    jobs::start_workers,
+    storage::max_upload_bytes,
+    watch::start_watchers,
 };
 use tower_http::cors::CorsLayer;
 
@@ -22,6 +26,16 @@ async fn main() -> anyhow::Result<()> {
     run_migrations(&pool).await?;
     println!("Migrations completed successfully!");
 
+    // Start background workers for queued uploads
+    let upload_workers = std::env::var("UPLOAD_WORKERS")
+        .ok()
+        .and_then(|s| s.parse::<usize>().ok())
+        .unwrap_or(2);
+    start_workers(pool.clone(), upload_workers).await?;
+
+    // Import files dropped into the directories listed in WATCH_DIRS
+    start_watchers(pool.clone()).await?;
+
     // Build CORS layer - allow frontend domains
     // Note: When allow_credentials(true), cannot use allow_origin(Any) or allow_headers(Any)
     // Must specify both origin and headers explicitly
@@ -32,12 +46,12 @@ async fn main() -> anyhow::Result<()> {
     // Create CORS layer that allows both URLs
     let cors = CorsLayer::new()
         .allow_origin(
-            tower_http::cors::AllowOrigin::predicate(move |origin: &axum::http::HeaderValue, _request_head: &axum::http::Request<()>| {
+            tower_http::cors::AllowOrigin::predicate(move |origin: &axum::http::HeaderValue, _request_head: &axum::http::request::Parts| {
                 let origin_str = origin.to_str().unwrap_or("");
                 origin_str == production_url || origin_str == preview_url
             })
         )
-        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
+        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::PATCH, Method::DELETE])
         .allow_headers([
             axum::http::header::CONTENT_TYPE,
             axum::http::header::AUTHORIZATION,
@@ -46,17 +60,34 @@ async fn main() -> anyhow::Result<()> {
         ])
         .allow_credentials(true);
 
+    // Upload routes stream to disk and enforce MAX_UPLOAD_BYTES themselves; the
+    // body limit leaves headroom for the other multipart fields.
+    let upload_body_limit = DefaultBodyLimit::max(max_upload_bytes() as usize + 64 * 1024);
+
     // Build application with routes
     let app = Router::new()
         .route("/api/health", get(health_check))
         .route("/api/dealers/register", post(register_dealer))
         .route("/api/dealers/login", post(login_dealer))
-        .route("/api/dealers/me", get(get_dealer_profile))
-        .route("/api/uploads", post(upload_file))
+        .route("/api/dealers/me", get(get_dealer_profile).patch(update_dealer_profile))
+        .route(
+            "/api/dealers/me/scoring",
+            get(get_scoring_settings).put(update_scoring_settings).delete(reset_scoring_settings),
+        )
+        .route("/api/scoring-models", get(list_scoring_models))
+        .route("/api/adapters", get(list_adapters))
+        .route("/api/uploads", post(upload_file).layer(upload_body_limit))
         .route("/api/uploads", get(list_uploads))
-        .route("/api/uploads/:id", get(get_upload))
+        .route("/api/uploads/preview", post(preview_upload).layer(upload_body_limit))
+        .route("/api/uploads/:id", get(get_upload).delete(delete_upload))
+        .route("/api/uploads/:id/reprocess", post(reprocess_upload))
+        .route("/api/uploads/:id/progress", get(upload_progress))
+        .route("/api/uploads/:id/errors", get(list_upload_errors))
+        .route("/api/uploads/:id/errors/download", get(download_upload_errors))
         .route("/api/vehicles", get(list_vehicles))
+        .route("/api/vehicles/batch", post(create_vehicle_batch).layer(upload_body_limit))
         .route("/api/vehicles/:id", get(get_vehicle))
+        .route("/api/telematics", post(ingest_telematics).layer(upload_body_limit))
         .route("/api/scored-leads", get(list_scored_leads))
         .route("/api/scored-leads/:id", get(get_scored_lead))
         .layer(cors)
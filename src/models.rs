@@ -1,3 +1,4 @@
+use crate::scoring::ScoringConfig;
 use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
@@ -11,6 +12,8 @@ pub struct Dealer {
     pub password_hash: String,
     pub zip_code: Option<String>,
     pub created_at: DateTime<Utc>,
+    pub date_order: String,
+    pub default_country: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -40,6 +43,52 @@ pub struct DealerResponse {
     pub email: String,
     pub zip_code: Option<String>,
     pub created_at: DateTime<Utc>,
+    pub date_order: String,
+    pub default_country: String,
+}
+
+/// `GET /api/dealers/me`: the dealer with its limits and current usage.
+#[derive(Debug, Serialize)]
+pub struct DealerProfile {
+    #[serde(flatten)]
+    pub dealer: DealerResponse,
+    pub quotas: DealerQuotas,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DealerQuotas {
+    pub rows_per_upload: QuotaUsage,
+    /// Uploads since midnight UTC; archives count once
+    pub uploads_per_day: QuotaUsage,
+    pub stored_vehicles: QuotaUsage,
+    /// Uploads being processed; further uploads wait in the queue
+    pub concurrent_jobs: QuotaUsage,
+    pub uploads_reset_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QuotaUsage {
+    pub limit: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub used: Option<i64>,
+}
+
+/// Settings a dealer can change with `PATCH /api/dealers/me`; omitted fields are left as they are.
+#[derive(Debug, Deserialize)]
+pub struct UpdateDealerRequest {
+    /// `mdy` or `dmy`
+    pub date_order: Option<String>,
+    /// ISO 3166 alpha-2 country used for phone numbers without a country code
+    pub default_country: Option<String>,
+}
+
+/// Body of `PUT /api/dealers/me/scoring`. The settings are replaced as a whole:
+/// an omitted strategy or config field takes its default.
+#[derive(Debug, Deserialize)]
+pub struct UpdateScoringRequest {
+    pub strategy: Option<String>,
+    #[serde(default)]
+    pub config: ScoringConfig,
 }
 
 impl From<Dealer> for DealerResponse {
@@ -50,6 +99,8 @@ impl From<Dealer> for DealerResponse {
             email: dealer.email,
             zip_code: dealer.zip_code,
             created_at: dealer.created_at,
+            date_order: dealer.date_order,
+            default_country: dealer.default_country,
         }
     }
 }
@@ -65,6 +116,72 @@ pub struct Upload {
     pub row_count: i32,
     pub processed_count: i32,
     pub error_message: Option<String>,
+    pub all_or_nothing: bool,
+    pub new_count: i32,
+    pub updated_count: i32,
+    pub unchanged_count: i32,
+    pub sheet_name: Option<String>,
+    pub phase: String,
+    pub rows_failed: i32,
+    /// Scoring version that produced this upload's leads
+    pub scoring_version: Option<String>,
+    pub reprocessed_at: Option<DateTime<Utc>>,
+    /// `file`, `api` or `watch`
+    pub source: String,
+    /// Import adapter that read the file, e.g. `generic` or `cdk`
+    pub adapter: Option<String>,
+    /// The archive upload this file was extracted from
+    pub parent_id: Option<i32>,
+    /// Number of files extracted, for an archive upload
+    pub child_count: i32,
+    /// Detected text encoding of a CSV file, e.g. `utf-8` or `windows-1252`
+    pub encoding: Option<String>,
+    /// Detected delimiter of a CSV file: `comma`, `semicolon`, `tab` or `pipe`
+    pub delimiter: Option<String>,
+    pub job_status: Option<String>,
+    pub job_mode: Option<String>,
+    pub job_attempts: Option<i32>,
+    pub job_run_after: Option<DateTime<Utc>>,
+    pub job_last_error: Option<String>,
+}
+
+/// Result of `DELETE /api/uploads/:id`.
+#[derive(Debug, Serialize)]
+pub struct UploadDeletion {
+    pub upload_id: i32,
+    pub filename: String,
+    /// Uploads of the files extracted from this archive, deleted with it
+    pub child_uploads_deleted: i64,
+    /// Vehicles that appeared only in this upload
+    pub vehicles_deleted: i64,
+    /// Vehicles that other uploads also contain, now attributed to the latest of them
+    pub vehicles_kept: i64,
+    pub file_removed: bool,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct UploadProgress {
+    pub upload_id: i32,
+    pub status: String,
+    pub phase: String,
+    pub rows_read: i32,
+    pub rows_processed: i32,
+    pub rows_failed: i32,
+    pub error_message: Option<String>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct UploadRowError {
+    pub id: i32,
+    pub upload_id: i32,
+    pub row_number: i32,
+    pub column_name: Option<String>,
+    pub raw_value: Option<String>,
+    pub reason: String,
+    pub rejected: bool,
+    #[serde(skip_serializing)]
+    pub raw_row: Option<String>,
+    pub created_at: DateTime<Utc>,
 }
 
 #[derive(Debug, Serialize, FromRow)]
@@ -73,26 +190,113 @@ pub struct Vehicle {
     pub upload_id: i32,
     pub dealer_id: i32,
     pub vin: String,
+    pub wmi: Option<String>,
+    pub manufacturer: Option<String>,
+    pub model_year: Option<i32>,
+    pub plant_code: Option<String>,
     pub warranty_exp_date: Option<NaiveDate>,
     pub customer_name: String,
     pub customer_phone: String,
+    pub customer_phone_ext: Option<String>,
     pub customer_email: Option<String>,
     pub customer_zip: Option<String>,
     pub last_service_date: Option<NaiveDate>,
+    pub contact_status: Option<String>,
     pub created_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VehicleInput {
     pub vin: String,
     pub warranty_exp_date: Option<String>,
     pub customer_name: String,
+    #[serde(default)]
     pub customer_phone: String,
     pub customer_email: Option<String>,
     pub customer_zip: Option<String>,
     pub last_service_date: Option<String>,
 }
 
+/// Response of `POST /api/vehicles/batch`: the upload queued for the batch and
+/// one result per submitted record, in order.
+#[derive(Debug, Serialize)]
+pub struct VehicleBatchResult {
+    pub upload: Upload,
+    pub records: Vec<BatchRecordResult>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchRecordResult {
+    /// Position of the record in the request, from 0
+    pub index: usize,
+    pub vin: Option<String>,
+    /// `queued`, or `rejected` when the record couldn't be read or its import will reject it
+    pub status: String,
+    /// Row of the upload's stored file the record was written to, for matching its row errors
+    pub row_number: Option<i32>,
+    pub errors: Vec<BatchRecordError>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchRecordError {
+    pub column: Option<String>,
+    pub raw_value: Option<String>,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UploadPreview {
+    pub adapter: String,
+    pub encoding: Option<String>,
+    pub delimiter: Option<String>,
+    pub column_mapping: Vec<ColumnMapping>,
+    pub total_rows: i32,
+    pub valid_rows: i32,
+    pub rejected_rows: i32,
+    pub rows: Vec<VehicleInput>,
+    pub issue_count: i32,
+    pub issues: Vec<PreviewIssue>,
+    pub score_distribution: ScoreDistribution,
+}
+
+/// An import adapter, as listed by `GET /api/adapters`.
+#[derive(Debug, Serialize)]
+pub struct AdapterInfo {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ColumnMapping {
+    pub column: String,
+    pub header: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PreviewIssue {
+    pub row_number: i32,
+    pub column_name: String,
+    pub raw_value: Option<String>,
+    pub reason: String,
+    pub rejected: bool,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ScoreDistribution {
+    pub min_urgency: Option<f32>,
+    pub max_urgency: Option<f32>,
+    pub avg_urgency: Option<f32>,
+    pub urgency_buckets: Vec<BucketCount>,
+    pub by_stressor_type: Vec<BucketCount>,
+    pub by_call_window_days: Vec<BucketCount>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BucketCount {
+    pub label: String,
+    pub count: i32,
+}
+
 #[derive(Debug, Serialize, FromRow)]
 pub struct ScoredLead {
     pub id: i32,
@@ -109,6 +313,19 @@ pub struct ScoredLead {
     pub call_by_date: NaiveDate,
     pub suggested_script: String,
     pub created_at: DateTime<Utc>,
+    pub scoring_version: Option<String>,
+    /// The `scoring_models` entry that scored this lead
+    pub model_id: Option<i32>,
+    /// Snapshot of the values the lead was scored from
+    pub inputs: Option<serde_json::Value>,
+    /// Each factor's input, bucket, weight and contribution to `urgency_score`
+    pub breakdown: Option<serde_json::Value>,
+    /// Whether the scores were projected to `as_of` for the response rather than stored
+    #[sqlx(default)]
+    pub projected: bool,
+    #[sqlx(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub as_of: Option<NaiveDate>,
 }
 
 #[derive(Debug, Serialize)]
@@ -118,6 +335,51 @@ pub struct ScoredLeadWithVehicle {
     pub vehicle: Vehicle,
 }
 
+/// One connected-vehicle reading, from `telematics_readings`.
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct TelematicsReading {
+    pub vin: String,
+    pub recorded_at: DateTime<Utc>,
+    pub odometer_miles: Option<i32>,
+    /// Diagnostic trouble codes active when the reading was taken
+    pub dtc_codes: Vec<String>,
+    pub battery_voltage: Option<f32>,
+    pub oil_life_percent: Option<f32>,
+}
+
+/// Outcome of `POST /api/telematics`.
+#[derive(Debug, Serialize)]
+pub struct TelematicsIngestResult {
+    pub stored: i32,
+    pub rejected: i32,
+    /// Leads rescored because their vehicle received a reading
+    pub leads_rescored: i32,
+    pub records: Vec<TelematicsRecordResult>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TelematicsRecordResult {
+    /// Position of the reading in the request, from 0
+    pub index: usize,
+    pub vin: Option<String>,
+    /// `stored` or `rejected`
+    pub status: String,
+    pub errors: Vec<String>,
+}
+
+/// A registered scoring model, with how many of the dealer's current leads it scored.
+#[derive(Debug, Serialize, FromRow)]
+pub struct ScoringModel {
+    pub id: i32,
+    pub scoring_version: String,
+    pub strategy: String,
+    pub config: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+    pub lead_count: i64,
+    /// Whether it matches the dealer's current scoring settings
+    pub active: bool,
+}
+
 #[derive(Debug, Serialize)]
 pub struct ApiResponse<T> {
     pub success: bool,
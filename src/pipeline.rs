@@ -0,0 +1,1018 @@
+use crate::adapters::{adapter_by_id, detect_adapter, ImportAdapter, GENERIC_ADAPTER};
+use crate::ingest::{ImportSettings, RowIssue, RowOutcome, RowValidator, ValidatedVehicle};
+use crate::quotas::DealerLimits;
+use crate::models::{
+    BatchRecordError, BatchRecordResult, BucketCount, ColumnMapping, Dealer, PreviewIssue, ScoreDistribution, UploadPreview,
+    Vehicle, VehicleInput,
+};
+use crate::scoring::{LeadScorer, LeadScores, ScoringStrategy, SCORING_VERSION};
+use crate::telematics::recent_readings;
+use crate::uploads::unlink_vehicles;
+use crate::reader::{open_records, SourceRow, TextFormat};
+use chrono::{NaiveDate, Utc};
+use csv::{StringRecord, WriterBuilder};
+use sqlx::{PgConnection, PgPool, Postgres, QueryBuilder};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use tokio::sync::mpsc;
+
+// Rows written per transaction. Inserts bind one array per column (UNNEST),
+// so the chunk size is bounded by memory rather than Postgres' bind limit.
+const CHUNK_SIZE: usize = 5000;
+// How often, in rows read, progress is written for `GET /api/uploads/:id/progress`
+const PROGRESS_EVERY: i32 = 1000;
+// Rows the file reader may validate ahead of the rows being written
+const READ_AHEAD_ROWS: usize = 1000;
+
+/// Parses, validates, scores and stores every row of an uploaded CSV or Excel file.
+///
+/// Rows are written in chunks of `CHUNK_SIZE`, each in its own transaction, and
+/// `processed_count` is advanced with every committed chunk. When the upload was
+/// created with `all_or_nothing`, the whole file is written in one transaction and
+/// any rejected row fails the upload without importing anything. A `reparse` of an
+/// existing upload is also written in one transaction, so its vehicles and leads
+/// are replaced all at once, but rejected rows don't fail it. Vehicles the upload
+/// contained that the file no longer gives are unlinked from it, as when an upload
+/// is deleted. Row errors are written every `CHUNK_SIZE` errors rather than held
+/// until the end of the file.
+pub async fn process_upload_file(
+    upload_id: i32,
+    file_path: &str,
+    dealer_id: i32,
+    reparse: bool,
+    pool: &PgPool,
+) -> anyhow::Result<()> {
+    let (all_or_nothing, sheet_name, adapter): (bool, Option<String>, Option<String>) =
+        sqlx::query_as("SELECT all_or_nothing, sheet_name, adapter FROM uploads WHERE id = $1")
+            .bind(upload_id)
+            .fetch_one(pool)
+            .await?;
+    let dealer = sqlx::query_as::<_, Dealer>(
+        "SELECT id, name, email, password_hash, zip_code, created_at, date_order, default_country FROM dealers WHERE id = $1"
+    )
+    .bind(dealer_id)
+    .fetch_one(pool)
+    .await?;
+    let settings = ImportSettings::for_dealer(&dealer);
+    let max_vehicles = DealerLimits::for_dealer(pool, dealer_id).await?.stored_vehicles;
+    // Every row of one run is scored as of the same day, even across midnight
+    let scorer = LeadScorer::registered(pool, dealer_id, Utc::now().date_naive()).await?;
+
+    // A retried job starts over. Vehicles an earlier attempt already wrote keep the
+    // change type that attempt gave them, so the counts match a run that never failed
+    sqlx::query("DELETE FROM upload_row_errors WHERE upload_id = $1")
+        .bind(upload_id)
+        .execute(pool)
+        .await?;
+    report_progress(pool, upload_id, "reading", 0, &ChunkSummary::default(), 0).await?;
+
+    // The file is read and validated on a blocking thread, a bounded number of rows ahead
+    let (sender, mut events) = mpsc::channel(READ_AHEAD_ROWS);
+    let reader = {
+        let file_path = file_path.to_string();
+        tokio::task::spawn_blocking(move || read_upload_rows(&file_path, sheet_name, adapter, settings, sender))
+    };
+    let (source_headers, adapter, text_format) = match events.recv().await {
+        Some(Ok(ReadEvent::Opened { source_headers, adapter, text_format })) => (source_headers, adapter, text_format),
+        Some(Err(e)) => return Err(e),
+        _ => {
+            reader.await?;
+            return Err(anyhow::anyhow!("The upload file reader stopped before opening the file"));
+        }
+    };
+
+    sqlx::query("UPDATE uploads SET source_headers = $1, adapter = $2, encoding = $3, delimiter = $4 WHERE id = $5")
+        .bind(source_headers)
+        .bind(adapter)
+        .bind(text_format.map(|f| f.encoding.as_str()))
+        .bind(text_format.map(|f| f.delimiter_name()))
+        .bind(upload_id)
+        .execute(pool)
+        .await?;
+
+    let mut row_count = 0;
+    let mut summary = ChunkSummary::default();
+    let mut rejected_count = 0;
+    let mut row_errors = Vec::new();
+    let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+    // A reparse drops the vehicles the file no longer gives it
+    let mut written_vins: Vec<String> = Vec::new();
+
+    let mut tx = pool.begin().await?;
+
+    while let Some(event) = events.recv().await {
+        let ReadEvent::Row { row_number, outcome, raw_row } = event? else {
+            continue;
+        };
+        row_count += 1;
+
+        if let Some(raw_row) = raw_row {
+            for issue in &outcome.issues {
+                row_errors.push(PendingRowError {
+                    row_number,
+                    issue: issue.clone(),
+                    rejected: outcome.is_rejected(),
+                    raw_row: raw_row.clone(),
+                });
+            }
+        }
+        // Row errors are written outside the import's transaction, so they are kept
+        // even when an all-or-nothing upload is rolled back and the file can be fixed
+        if row_errors.len() >= CHUNK_SIZE {
+            insert_row_errors(upload_id, &row_errors, pool).await?;
+            row_errors.clear();
+        }
+        match outcome.vehicle {
+            Some(row) => chunk.push(row),
+            None => rejected_count += 1,
+        }
+        if row_count % PROGRESS_EVERY == 0 {
+            report_progress(pool, upload_id, "importing", row_count, &summary, rejected_count).await?;
+        }
+
+        if chunk.len() >= CHUNK_SIZE {
+            if reparse {
+                written_vins.extend(chunk.iter().map(|row| row.vin_info.vin.clone()));
+            }
+            summary += write_chunk(&mut tx, upload_id, dealer_id, max_vehicles, &scorer, &chunk).await?;
+            chunk.clear();
+            if !(all_or_nothing || reparse) {
+                commit_progress(tx, upload_id, row_count, &summary, rejected_count).await?;
+                tx = pool.begin().await?;
+            }
+        }
+    }
+    // A reader that panicked closes the channel too, so it mustn't pass for the end of the file
+    reader.await?;
+
+    report_progress(pool, upload_id, "finalizing", row_count, &summary, rejected_count).await?;
+    insert_row_errors(upload_id, &row_errors, pool).await?;
+
+    if all_or_nothing && rejected_count > 0 {
+        tx.rollback().await?;
+        sqlx::query("UPDATE uploads SET row_count = $1, processed_count = 0, new_count = 0, updated_count = 0, unchanged_count = 0, rows_failed = $2 WHERE id = $3")
+            .bind(row_count)
+            .bind(rejected_count)
+            .bind(upload_id)
+            .execute(pool)
+            .await?;
+        return Err(anyhow::anyhow!(
+            "{} row(s) failed validation; nothing was imported because the upload is all-or-nothing",
+            rejected_count
+        ));
+    }
+
+    summary += write_chunk(&mut tx, upload_id, dealer_id, max_vehicles, &scorer, &chunk).await?;
+    if reparse {
+        written_vins.extend(chunk.iter().map(|row| row.vin_info.vin.clone()));
+        unlink_vehicles(&mut tx, upload_id, dealer_id, &written_vins).await?;
+    }
+
+    // Update upload status
+    sqlx::query(
+        "UPDATE uploads SET status = 'completed', phase = 'completed', row_count = $1, processed_count = $2, new_count = $3, updated_count = $4, unchanged_count = $5, rows_failed = $6, \
+         scoring_version = $7, reprocessed_at = CASE WHEN $8 THEN NOW() ELSE reprocessed_at END WHERE id = $9"
+    )
+    .bind(row_count)
+    .bind(summary.processed())
+    .bind(summary.new)
+    .bind(summary.updated)
+    .bind(summary.unchanged)
+    .bind(rejected_count)
+    .bind(SCORING_VERSION)
+    .bind(reparse)
+    .bind(upload_id)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+// What the reader of an upload file sends `process_upload_file`: first the file's
+// header row and how it was read, then each data row once validated, with the row
+// as CSV when it has issues to record.
+enum ReadEvent {
+    Opened {
+        source_headers: String,
+        adapter: &'static str,
+        text_format: Option<TextFormat>,
+    },
+    Row {
+        row_number: i32,
+        outcome: Box<RowOutcome>,
+        raw_row: Option<String>,
+    },
+}
+
+// Opens, decodes and validates an upload file for `process_upload_file`, on a
+// blocking thread. Stops reading once the import has stopped receiving.
+fn read_upload_rows(
+    file_path: &str,
+    sheet_name: Option<String>,
+    adapter: Option<String>,
+    settings: ImportSettings,
+    sender: mpsc::Sender<anyhow::Result<ReadEvent>>,
+) {
+    let read = || -> anyhow::Result<()> {
+        let (headers, rows, text_format) = open_records(file_path, sheet_name.as_deref())?;
+        let adapter = resolve_adapter(adapter.as_deref(), &headers)?;
+        let mut validator = RowValidator::new(adapter.bind(&headers)?, settings);
+        let opened = ReadEvent::Opened {
+            source_headers: encode_csv_record(&headers)?,
+            adapter: adapter.id(),
+            text_format,
+        };
+        if sender.blocking_send(Ok(opened)).is_err() {
+            return Ok(());
+        }
+
+        for result in rows {
+            let SourceRow { row_number, record } = result?;
+            let outcome = validator.validate(row_number, &record);
+            let raw_row = if outcome.issues.is_empty() {
+                None
+            } else {
+                Some(encode_csv_record(&record)?)
+            };
+            if sender.blocking_send(Ok(ReadEvent::Row { row_number, outcome: Box::new(outcome), raw_row })).is_err() {
+                return Ok(());
+            }
+        }
+        Ok(())
+    };
+    if let Err(e) = read() {
+        let _ = sender.blocking_send(Err(e));
+    }
+}
+
+/// Rescores every vehicle an upload contains from the data already on file,
+/// replacing their leads in one transaction. The file is not read again.
+pub async fn rescore_upload(upload_id: i32, dealer_id: i32, pool: &PgPool) -> anyhow::Result<()> {
+    report_progress(pool, upload_id, "scoring", 0, &ChunkSummary::default(), 0).await?;
+    let scorer = LeadScorer::registered(pool, dealer_id, Utc::now().date_naive()).await?;
+
+    let mut tx = pool.begin().await?;
+    // Keep the dealer's other uploads from changing these vehicles mid-rescore
+    sqlx::query("SELECT pg_advisory_xact_lock($1)")
+        .bind(dealer_id as i64)
+        .execute(&mut *tx)
+        .await?;
+
+    let mut last_id = 0;
+    loop {
+        let vehicles = sqlx::query_as::<_, Vehicle>(
+            "SELECT v.id, v.upload_id, v.dealer_id, v.vin, v.warranty_exp_date, v.customer_name, v.customer_phone, v.customer_email, v.customer_zip, v.last_service_date, v.created_at, v.wmi, v.manufacturer, v.model_year, v.plant_code, v.customer_phone_ext, v.contact_status \
+             FROM vehicles v JOIN vehicle_uploads l ON l.vehicle_id = v.id \
+             WHERE l.upload_id = $1 AND v.id > $2 ORDER BY v.id LIMIT $3"
+        )
+        .bind(upload_id)
+        .bind(last_id)
+        .bind(CHUNK_SIZE as i64)
+        .fetch_all(&mut *tx)
+        .await?;
+        let Some(last) = vehicles.last() else { break };
+        last_id = last.id;
+
+        score_vehicles(&mut tx, dealer_id, &scorer, &vehicles).await?;
+    }
+
+    sqlx::query("UPDATE uploads SET status = 'completed', phase = 'completed', scoring_version = $1, reprocessed_at = NOW() WHERE id = $2")
+        .bind(SCORING_VERSION)
+        .bind(upload_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+// Header row of the CSV file an API batch is stored as; these are the canonical column names.
+const BATCH_HEADERS: [&str; 7] = [
+    "vin",
+    "warranty_exp_date",
+    "customer_name",
+    "customer_phone",
+    "customer_email",
+    "customer_zip",
+    "last_service_date",
+];
+
+/// Stores API batch records as a CSV file with the canonical headers, so the batch
+/// is imported, reported on and reparsed exactly like an uploaded file. Record `i`
+/// is on row `i + 2` of the file.
+pub fn write_batch_file(path: &Path, records: &[&VehicleInput]) -> anyhow::Result<()> {
+    let mut writer = WriterBuilder::new().from_path(path)?;
+    writer.write_record(BATCH_HEADERS)?;
+    for record in records {
+        writer.write_record(&batch_row(record))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+// A batch record as `write_batch_file` stores it
+fn batch_row(record: &VehicleInput) -> StringRecord {
+    // Line breaks inside a value would move every later record down a row
+    fn field(value: &str) -> String {
+        value.replace(['\r', '\n'], " ")
+    }
+    fn opt_field(value: &Option<String>) -> String {
+        value.as_deref().map(field).unwrap_or_default()
+    }
+
+    StringRecord::from(vec![
+        field(&record.vin),
+        opt_field(&record.warranty_exp_date),
+        field(&record.customer_name),
+        field(&record.customer_phone),
+        opt_field(&record.customer_email),
+        opt_field(&record.customer_zip),
+        opt_field(&record.last_service_date),
+    ])
+}
+
+/// Validates API batch records as their import will, returning each record's result
+/// in order. `records` holds every submitted record, or why it couldn't be read. The
+/// readable ones are validated as the rows `write_batch_file` stores them on, with
+/// the generic adapter and the dealer's settings, so a record is `rejected` here
+/// exactly when its import will reject it, and its `errors` are the row errors the
+/// upload will record for it. The rest are `queued`, still listing any values that
+/// will be dropped or guessed.
+pub fn validate_batch_records(
+    records: &[Result<VehicleInput, String>],
+    settings: ImportSettings,
+) -> anyhow::Result<Vec<BatchRecordResult>> {
+    let headers = StringRecord::from(BATCH_HEADERS.to_vec());
+    let adapter = resolve_adapter(Some(GENERIC_ADAPTER), &headers)?;
+    let mut validator = RowValidator::new(adapter.bind(&headers)?, settings);
+    let mut row_number = 1;
+    Ok(records
+        .iter()
+        .enumerate()
+        .map(|(index, record)| match record {
+            Ok(input) => {
+                row_number += 1;
+                let outcome = validator.validate(row_number, &batch_row(input));
+                BatchRecordResult {
+                    index,
+                    vin: Some(input.vin.clone()),
+                    status: if outcome.is_rejected() { "rejected" } else { "queued" }.to_string(),
+                    row_number: Some(row_number),
+                    errors: outcome
+                        .issues
+                        .into_iter()
+                        .map(|issue| BatchRecordError {
+                            column: Some(issue.column),
+                            raw_value: issue.raw_value,
+                            reason: issue.reason,
+                        })
+                        .collect(),
+                }
+            }
+            Err(reason) => BatchRecordResult {
+                index,
+                vin: None,
+                status: "rejected".to_string(),
+                row_number: None,
+                errors: vec![BatchRecordError {
+                    column: None,
+                    raw_value: None,
+                    reason: reason.clone(),
+                }],
+            },
+        })
+        .collect())
+}
+
+// The adapter chosen for an upload, or the one its header row matches when none was.
+fn resolve_adapter(requested: Option<&str>, headers: &StringRecord) -> anyhow::Result<&'static dyn ImportAdapter> {
+    match requested {
+        Some(id) => adapter_by_id(id).ok_or_else(|| anyhow::anyhow!("Unknown import adapter: {}", id)),
+        None => Ok(detect_adapter(headers)),
+    }
+}
+
+// Cap on issues returned by a preview; `issue_count` still reports the total.
+const MAX_PREVIEW_ISSUES: usize = 1000;
+
+/// Runs an upload file through the same parsing, validation and scoring as
+/// `process_upload_file` without writing anything, returning the first
+/// `row_limit` parsed rows and the score distribution over all valid rows.
+/// Rows are scored with their vehicles' recent telematics readings, as on import.
+#[allow(clippy::too_many_arguments)]
+pub async fn preview_upload_file(
+    pool: &PgPool,
+    file_path: String,
+    sheet_name: Option<String>,
+    adapter: Option<String>,
+    dealer_id: i32,
+    settings: ImportSettings,
+    scoring: Box<dyn ScoringStrategy>,
+    row_limit: usize,
+) -> anyhow::Result<UploadPreview> {
+    let as_of = Utc::now().date_naive();
+    let (mut preview, vehicles) = tokio::task::spawn_blocking(move || {
+        parse_preview(&file_path, sheet_name.as_deref(), adapter.as_deref(), dealer_id, settings, row_limit)
+    })
+    .await??;
+
+    let vins: Vec<String> = vehicles.iter().map(|v| v.vin.clone()).collect();
+    let mut conn = pool.acquire().await?;
+    let readings = recent_readings(&mut conn, dealer_id, Some(&vins), as_of).await?;
+    let scores: Vec<LeadScores> = vehicles
+        .iter()
+        .map(|vehicle| scoring.score(vehicle, readings.get(&vehicle.vin), as_of))
+        .collect();
+    preview.score_distribution = score_distribution(&scores, as_of);
+    Ok(preview)
+}
+
+// Parses and validates a file for `preview_upload_file`, returning the preview
+// without its score distribution and the valid rows as unsaved vehicles.
+fn parse_preview(
+    file_path: &str,
+    sheet_name: Option<&str>,
+    adapter: Option<&str>,
+    dealer_id: i32,
+    settings: ImportSettings,
+    row_limit: usize,
+) -> anyhow::Result<(UploadPreview, Vec<Vehicle>)> {
+    let (headers, rows, text_format) = open_records(file_path, sheet_name)?;
+    let adapter = resolve_adapter(adapter, &headers)?;
+    let mut validator = RowValidator::new(adapter.bind(&headers)?, settings);
+
+    let column_mapping = validator
+        .mapper()
+        .mapping()
+        .into_iter()
+        .map(|(column, header)| ColumnMapping {
+            column: column.to_string(),
+            header,
+        })
+        .collect();
+
+    let mut preview_rows = Vec::new();
+    let mut issues = Vec::new();
+    let mut issue_count = 0;
+    let mut total_rows = 0;
+    let mut valid_rows = 0;
+    let mut vehicles = Vec::new();
+
+    for result in rows {
+        let SourceRow { row_number, record } = result?;
+        total_rows += 1;
+
+        if preview_rows.len() < row_limit {
+            preview_rows.push(validator.mapper().to_input(&record));
+        }
+
+        let outcome = validator.validate(row_number, &record);
+        issue_count += outcome.issues.len() as i32;
+        for issue in &outcome.issues {
+            if issues.len() < MAX_PREVIEW_ISSUES {
+                issues.push(PreviewIssue {
+                    row_number,
+                    column_name: issue.column.clone(),
+                    raw_value: issue.raw_value.clone(),
+                    reason: issue.reason.clone(),
+                    rejected: outcome.is_rejected(),
+                });
+            }
+        }
+
+        if let Some(row) = outcome.vehicle {
+            valid_rows += 1;
+            vehicles.push(unsaved_vehicle(&row, dealer_id));
+        }
+    }
+
+    let preview = UploadPreview {
+        adapter: adapter.id().to_string(),
+        encoding: text_format.map(|f| f.encoding.as_str().to_string()),
+        delimiter: text_format.map(|f| f.delimiter_name().to_string()),
+        column_mapping,
+        total_rows,
+        valid_rows,
+        rejected_rows: total_rows - valid_rows,
+        rows: preview_rows,
+        issue_count,
+        issues,
+        score_distribution: ScoreDistribution::default(),
+    };
+    Ok((preview, vehicles))
+}
+
+// A vehicle as it would be inserted, for scoring rows that aren't stored.
+fn unsaved_vehicle(row: &ValidatedVehicle, dealer_id: i32) -> Vehicle {
+    Vehicle {
+        id: 0,
+        upload_id: 0,
+        dealer_id,
+        vin: row.vin_info.vin.clone(),
+        wmi: Some(row.vin_info.wmi.clone()),
+        manufacturer: row.vin_info.manufacturer.clone(),
+        model_year: row.vin_info.model_year,
+        plant_code: Some(row.vin_info.plant_code.clone()),
+        warranty_exp_date: row.warranty_exp_date,
+        customer_name: row.customer_name.clone(),
+        customer_phone: row.customer_phone.clone(),
+        customer_phone_ext: row.customer_phone_ext.clone(),
+        customer_email: row.customer_email.clone(),
+        customer_zip: row.customer_zip.clone(),
+        last_service_date: row.last_service_date,
+        contact_status: Some(row.contact_status.as_str().to_string()),
+        created_at: Utc::now(),
+    }
+}
+
+fn score_distribution(scores: &[LeadScores], as_of: NaiveDate) -> ScoreDistribution {
+    if scores.is_empty() {
+        return ScoreDistribution::default();
+    }
+
+    let urgencies: Vec<f32> = scores.iter().map(|s| s.urgency_score).collect();
+    let mut bands = [0; 5];
+    for urgency in &urgencies {
+        bands[((urgency * 5.0) as usize).min(4)] += 1;
+    }
+    let urgency_buckets = bands
+        .iter()
+        .enumerate()
+        .map(|(i, &count)| BucketCount {
+            label: format!("{:.1}-{:.1}", i as f32 * 0.2, (i + 1) as f32 * 0.2),
+            count,
+        })
+        .collect();
+
+    let mut by_stressor_type: BTreeMap<String, i32> = BTreeMap::new();
+    let mut by_call_window: BTreeMap<i64, i32> = BTreeMap::new();
+    for score in scores {
+        let stressor = score.stressor_type.clone().unwrap_or_else(|| "None".to_string());
+        *by_stressor_type.entry(stressor).or_default() += 1;
+        *by_call_window.entry((score.call_by_date - as_of).num_days()).or_default() += 1;
+    }
+
+    ScoreDistribution {
+        min_urgency: urgencies.iter().copied().reduce(f32::min),
+        max_urgency: urgencies.iter().copied().reduce(f32::max),
+        avg_urgency: Some(urgencies.iter().sum::<f32>() / urgencies.len() as f32),
+        urgency_buckets,
+        by_stressor_type: by_stressor_type
+            .into_iter()
+            .map(|(label, count)| BucketCount { label, count })
+            .collect(),
+        by_call_window_days: by_call_window
+            .into_iter()
+            .map(|(days, count)| BucketCount {
+                label: days.to_string(),
+                count,
+            })
+            .collect(),
+    }
+}
+
+async fn commit_progress(
+    mut tx: sqlx::Transaction<'_, Postgres>,
+    upload_id: i32,
+    row_count: i32,
+    summary: &ChunkSummary,
+    rows_failed: i32,
+) -> anyhow::Result<()> {
+    sqlx::query("UPDATE uploads SET row_count = $1, processed_count = $2, new_count = $3, updated_count = $4, unchanged_count = $5, rows_failed = $6 WHERE id = $7")
+        .bind(row_count)
+        .bind(summary.processed())
+        .bind(summary.new)
+        .bind(summary.updated)
+        .bind(summary.unchanged)
+        .bind(rows_failed)
+        .bind(upload_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+// Written outside the upload's transaction so progress is visible while an
+// all-or-nothing upload is still uncommitted.
+async fn report_progress(
+    pool: &PgPool,
+    upload_id: i32,
+    phase: &str,
+    row_count: i32,
+    summary: &ChunkSummary,
+    rows_failed: i32,
+) -> anyhow::Result<()> {
+    sqlx::query("UPDATE uploads SET phase = $1, row_count = $2, processed_count = $3, rows_failed = $4 WHERE id = $5")
+        .bind(phase)
+        .bind(row_count)
+        .bind(summary.processed())
+        .bind(rows_failed)
+        .bind(upload_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// How the rows of an upload compared with the vehicles already on file.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ChunkSummary {
+    pub new: i32,
+    pub updated: i32,
+    pub unchanged: i32,
+}
+
+impl ChunkSummary {
+    pub fn processed(&self) -> i32 {
+        self.new + self.updated + self.unchanged
+    }
+}
+
+impl std::ops::AddAssign for ChunkSummary {
+    fn add_assign(&mut self, other: Self) {
+        self.new += other.new;
+        self.updated += other.updated;
+        self.unchanged += other.unchanged;
+    }
+}
+
+/// Upserts a chunk of vehicles by `(dealer_id, vin)`, links each one to the upload
+/// and regenerates its scored lead. Rows must have distinct VINs.
+async fn write_chunk(
+    conn: &mut PgConnection,
+    upload_id: i32,
+    dealer_id: i32,
+    max_vehicles: i64,
+    scorer: &LeadScorer,
+    rows: &[ValidatedVehicle],
+) -> anyhow::Result<ChunkSummary> {
+    let mut summary = ChunkSummary::default();
+    if rows.is_empty() {
+        return Ok(summary);
+    }
+
+    // Serialize a dealer's concurrent uploads so two of them can't both insert the same new VIN
+    sqlx::query("SELECT pg_advisory_xact_lock($1)")
+        .bind(dealer_id as i64)
+        .execute(&mut *conn)
+        .await?;
+
+    let existing = sqlx::query_as::<_, Vehicle>(
+        "SELECT id, upload_id, dealer_id, vin, warranty_exp_date, customer_name, customer_phone, customer_email, customer_zip, last_service_date, created_at, wmi, manufacturer, model_year, plant_code, customer_phone_ext, contact_status FROM vehicles WHERE dealer_id = $1 AND vin = ANY($2) FOR UPDATE"
+    )
+    .bind(dealer_id)
+    .bind(rows.iter().map(|r| r.vin_info.vin.clone()).collect::<Vec<_>>())
+    .fetch_all(&mut *conn)
+    .await?;
+    let existing: HashMap<&str, &Vehicle> = existing.iter().map(|v| (v.vin.as_str(), v)).collect();
+
+    let mut new_rows = Vec::new();
+    let mut existing_rows = Vec::new();
+    let mut changes = Vec::new();
+    for row in rows {
+        match existing.get(row.vin_info.vin.as_str()) {
+            None => new_rows.push(row),
+            Some(vehicle) => {
+                let change_type = if has_changes(vehicle, row) { "updated" } else { "unchanged" };
+                existing_rows.push((vehicle.id, row));
+                changes.push((vehicle.id, change_type));
+            }
+        }
+    }
+
+    if !new_rows.is_empty() {
+        let stored: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM vehicles WHERE dealer_id = $1")
+            .bind(dealer_id)
+            .fetch_one(&mut *conn)
+            .await?;
+        if stored + new_rows.len() as i64 > max_vehicles {
+            return Err(anyhow::anyhow!(
+                "Importing this file would take you past your limit of {} stored vehicles ({} stored now)",
+                max_vehicles,
+                stored
+            ));
+        }
+    }
+
+    let mut vehicles = insert_vehicles(conn, upload_id, dealer_id, &new_rows).await?;
+    changes.extend(vehicles.iter().map(|v| (v.id, "new")));
+    vehicles.extend(update_vehicles(conn, upload_id, &existing_rows).await?);
+
+    // A vehicle this upload already linked keeps the change it was first linked with,
+    // and is counted as that change again
+    let linked: Vec<String> = sqlx::query_scalar(
+        "INSERT INTO vehicle_uploads (vehicle_id, upload_id, change_type) \
+         SELECT vehicle_id, $1, change_type FROM UNNEST($2::INTEGER[], $3::VARCHAR[]) AS t(vehicle_id, change_type) \
+         ON CONFLICT (vehicle_id, upload_id) DO UPDATE SET change_type = vehicle_uploads.change_type \
+         RETURNING change_type"
+    )
+    .bind(upload_id)
+    .bind(changes.iter().map(|(id, _)| *id).collect::<Vec<_>>())
+    .bind(changes.iter().map(|(_, change)| change.to_string()).collect::<Vec<_>>())
+    .fetch_all(&mut *conn)
+    .await?;
+    for change_type in &linked {
+        match change_type.as_str() {
+            "new" => summary.new += 1,
+            "updated" => summary.updated += 1,
+            _ => summary.unchanged += 1,
+        }
+    }
+
+    score_vehicles(conn, dealer_id, scorer, &vehicles).await?;
+
+    Ok(summary)
+}
+
+// Scores vehicles with their recent telematics readings and stores their leads.
+async fn score_vehicles(
+    conn: &mut PgConnection,
+    dealer_id: i32,
+    scorer: &LeadScorer,
+    vehicles: &[Vehicle],
+) -> anyhow::Result<()> {
+    let vins: Vec<String> = vehicles.iter().map(|v| v.vin.clone()).collect();
+    let readings = recent_readings(conn, dealer_id, Some(&vins), scorer.as_of).await?;
+    let scored: Vec<(&Vehicle, LeadScores)> = vehicles
+        .iter()
+        .map(|vehicle| (vehicle, scorer.score(vehicle, readings.get(&vehicle.vin))))
+        .collect();
+    upsert_scored_leads(conn, scorer.model_id, &scored).await
+}
+
+/// Rescores the dealer's vehicles with the given VINs, as after new telematics
+/// readings arrive for them. Returns how many leads were rescored.
+pub async fn rescore_vehicles(pool: &PgPool, dealer_id: i32, vins: &[String]) -> anyhow::Result<usize> {
+    let scorer = LeadScorer::registered(pool, dealer_id, Utc::now().date_naive()).await?;
+
+    let mut tx = pool.begin().await?;
+    // Keep the dealer's uploads from changing these vehicles mid-rescore
+    sqlx::query("SELECT pg_advisory_xact_lock($1)")
+        .bind(dealer_id as i64)
+        .execute(&mut *tx)
+        .await?;
+
+    let vehicles = sqlx::query_as::<_, Vehicle>(
+        "SELECT id, upload_id, dealer_id, vin, warranty_exp_date, customer_name, customer_phone, customer_email, customer_zip, last_service_date, created_at, wmi, manufacturer, model_year, plant_code, customer_phone_ext, contact_status FROM vehicles WHERE dealer_id = $1 AND vin = ANY($2)"
+    )
+    .bind(dealer_id)
+    .bind(vins)
+    .fetch_all(&mut *tx)
+    .await?;
+    if !vehicles.is_empty() {
+        score_vehicles(&mut tx, dealer_id, &scorer, &vehicles).await?;
+    }
+    tx.commit().await?;
+
+    Ok(vehicles.len())
+}
+
+// Blank optional values in a newer upload keep what is already on file,
+// so they don't count as changes either.
+fn has_changes(vehicle: &Vehicle, row: &ValidatedVehicle) -> bool {
+    fn differs<T: PartialEq>(new: &Option<T>, old: &Option<T>) -> bool {
+        new.is_some() && new != old
+    }
+
+    row.customer_name != vehicle.customer_name
+        || row.customer_phone != vehicle.customer_phone
+        || row.customer_phone_ext != vehicle.customer_phone_ext
+        || vehicle.contact_status.as_deref() != Some(row.contact_status.as_str())
+        || differs(&row.customer_email, &vehicle.customer_email)
+        || differs(&row.customer_zip, &vehicle.customer_zip)
+        || differs(&row.warranty_exp_date, &vehicle.warranty_exp_date)
+        || differs(&row.last_service_date, &vehicle.last_service_date)
+}
+
+async fn insert_vehicles(
+    conn: &mut PgConnection,
+    upload_id: i32,
+    dealer_id: i32,
+    rows: &[&ValidatedVehicle],
+) -> anyhow::Result<Vec<Vehicle>> {
+    if rows.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    // Allocate ids up front so the returned rows can be matched back to the input
+    // without relying on the order of RETURNING.
+    let ids: Vec<i32> = sqlx::query_scalar("SELECT nextval('vehicles_id_seq')::INTEGER FROM generate_series(1, $1)")
+        .bind(rows.len() as i32)
+        .fetch_all(&mut *conn)
+        .await?;
+
+    let inserted = sqlx::query_as::<_, Vehicle>(
+        "INSERT INTO vehicles (id, upload_id, dealer_id, vin, warranty_exp_date, customer_name, customer_phone, customer_email, customer_zip, last_service_date, wmi, manufacturer, model_year, plant_code, customer_phone_ext, contact_status) \
+         SELECT id, $1, $2, vin, warranty_exp_date, customer_name, customer_phone, customer_email, customer_zip, last_service_date, wmi, manufacturer, model_year, plant_code, customer_phone_ext, contact_status \
+         FROM UNNEST($3::INTEGER[], $4::VARCHAR[], $5::DATE[], $6::VARCHAR[], $7::VARCHAR[], $8::VARCHAR[], $9::VARCHAR[], $10::DATE[], $11::VARCHAR[], $12::VARCHAR[], $13::INTEGER[], $14::VARCHAR[], $15::VARCHAR[], $16::VARCHAR[]) \
+         AS t(id, vin, warranty_exp_date, customer_name, customer_phone, customer_email, customer_zip, last_service_date, wmi, manufacturer, model_year, plant_code, customer_phone_ext, contact_status) \
+         RETURNING id, upload_id, dealer_id, vin, warranty_exp_date, customer_name, customer_phone, customer_email, customer_zip, last_service_date, created_at, wmi, manufacturer, model_year, plant_code, customer_phone_ext, contact_status"
+    )
+    .bind(upload_id)
+    .bind(dealer_id)
+    .bind(&ids)
+    .bind(rows.iter().map(|r| r.vin_info.vin.clone()).collect::<Vec<_>>())
+    .bind(rows.iter().map(|r| r.warranty_exp_date).collect::<Vec<_>>())
+    .bind(rows.iter().map(|r| r.customer_name.clone()).collect::<Vec<_>>())
+    .bind(rows.iter().map(|r| r.customer_phone.clone()).collect::<Vec<_>>())
+    .bind(rows.iter().map(|r| r.customer_email.clone()).collect::<Vec<_>>())
+    .bind(rows.iter().map(|r| r.customer_zip.clone()).collect::<Vec<_>>())
+    .bind(rows.iter().map(|r| r.last_service_date).collect::<Vec<_>>())
+    .bind(rows.iter().map(|r| r.vin_info.wmi.clone()).collect::<Vec<_>>())
+    .bind(rows.iter().map(|r| r.vin_info.manufacturer.clone()).collect::<Vec<_>>())
+    .bind(rows.iter().map(|r| r.vin_info.model_year).collect::<Vec<_>>())
+    .bind(rows.iter().map(|r| r.vin_info.plant_code.clone()).collect::<Vec<_>>())
+    .bind(rows.iter().map(|r| r.customer_phone_ext.clone()).collect::<Vec<_>>())
+    .bind(rows.iter().map(|r| r.contact_status.as_str()).collect::<Vec<_>>())
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut by_id: HashMap<i32, Vehicle> = inserted.into_iter().map(|v| (v.id, v)).collect();
+    ids.iter()
+        .map(|id| {
+            by_id
+                .remove(id)
+                .ok_or_else(|| anyhow::anyhow!("Inserted vehicle {} was not returned", id))
+        })
+        .collect()
+}
+
+async fn update_vehicles(
+    conn: &mut PgConnection,
+    upload_id: i32,
+    rows: &[(i32, &ValidatedVehicle)],
+) -> anyhow::Result<Vec<Vehicle>> {
+    if rows.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let updated = sqlx::query_as::<_, Vehicle>(
+        "UPDATE vehicles v SET upload_id = $1, \
+         customer_name = t.customer_name, customer_phone = t.customer_phone, \
+         customer_phone_ext = t.customer_phone_ext, contact_status = t.contact_status, \
+         customer_email = COALESCE(t.customer_email, v.customer_email), \
+         customer_zip = COALESCE(t.customer_zip, v.customer_zip), \
+         warranty_exp_date = COALESCE(t.warranty_exp_date, v.warranty_exp_date), \
+         last_service_date = COALESCE(t.last_service_date, v.last_service_date), \
+         wmi = t.wmi, manufacturer = t.manufacturer, model_year = t.model_year, plant_code = t.plant_code \
+         FROM UNNEST($2::INTEGER[], $3::DATE[], $4::VARCHAR[], $5::VARCHAR[], $6::VARCHAR[], $7::VARCHAR[], $8::DATE[], $9::VARCHAR[], $10::VARCHAR[], $11::INTEGER[], $12::VARCHAR[], $13::VARCHAR[], $14::VARCHAR[]) \
+         AS t(id, warranty_exp_date, customer_name, customer_phone, customer_email, customer_zip, last_service_date, wmi, manufacturer, model_year, plant_code, customer_phone_ext, contact_status) \
+         WHERE v.id = t.id \
+         RETURNING v.id, v.upload_id, v.dealer_id, v.vin, v.warranty_exp_date, v.customer_name, v.customer_phone, v.customer_email, v.customer_zip, v.last_service_date, v.created_at, v.wmi, v.manufacturer, v.model_year, v.plant_code, v.customer_phone_ext, v.contact_status"
+    )
+    .bind(upload_id)
+    .bind(rows.iter().map(|(id, _)| *id).collect::<Vec<_>>())
+    .bind(rows.iter().map(|(_, r)| r.warranty_exp_date).collect::<Vec<_>>())
+    .bind(rows.iter().map(|(_, r)| r.customer_name.clone()).collect::<Vec<_>>())
+    .bind(rows.iter().map(|(_, r)| r.customer_phone.clone()).collect::<Vec<_>>())
+    .bind(rows.iter().map(|(_, r)| r.customer_email.clone()).collect::<Vec<_>>())
+    .bind(rows.iter().map(|(_, r)| r.customer_zip.clone()).collect::<Vec<_>>())
+    .bind(rows.iter().map(|(_, r)| r.last_service_date).collect::<Vec<_>>())
+    .bind(rows.iter().map(|(_, r)| r.vin_info.wmi.clone()).collect::<Vec<_>>())
+    .bind(rows.iter().map(|(_, r)| r.vin_info.manufacturer.clone()).collect::<Vec<_>>())
+    .bind(rows.iter().map(|(_, r)| r.vin_info.model_year).collect::<Vec<_>>())
+    .bind(rows.iter().map(|(_, r)| r.vin_info.plant_code.clone()).collect::<Vec<_>>())
+    .bind(rows.iter().map(|(_, r)| r.customer_phone_ext.clone()).collect::<Vec<_>>())
+    .bind(rows.iter().map(|(_, r)| r.contact_status.as_str()).collect::<Vec<_>>())
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(updated)
+}
+
+// Each vehicle has a single lead, which is rescored in place and attributed to
+// the vehicle's latest upload.
+async fn upsert_scored_leads(
+    conn: &mut PgConnection,
+    model_id: Option<i32>,
+    scored: &[(&Vehicle, LeadScores)],
+) -> anyhow::Result<()> {
+    let inputs = scored
+        .iter()
+        .map(|(_, s)| serde_json::to_value(&s.inputs))
+        .collect::<Result<Vec<_>, _>>()?;
+    let breakdowns = scored
+        .iter()
+        .map(|(_, s)| serde_json::to_value(&s.breakdown))
+        .collect::<Result<Vec<_>, _>>()?;
+    sqlx::query(
+        "INSERT INTO scored_leads (vehicle_id, upload_id, urgency_score, stressor_score, warranty_score, susceptibility_score, telematic_score, has_telematic, stressor_type, why_now, call_by_date, suggested_script, scoring_version, model_id, inputs, breakdown) \
+         SELECT vehicle_id, upload_id, urgency_score, stressor_score, warranty_score, susceptibility_score, telematic_score, has_telematic, stressor_type, why_now, call_by_date, suggested_script, $1, $14, inputs, breakdown \
+         FROM UNNEST($2::INTEGER[], $3::INTEGER[], $4::REAL[], $5::REAL[], $6::REAL[], $7::REAL[], $8::REAL[], $9::BOOLEAN[], $10::VARCHAR[], $11::TEXT[], $12::DATE[], $13::TEXT[], $15::JSONB[], $16::JSONB[]) \
+         AS t(vehicle_id, upload_id, urgency_score, stressor_score, warranty_score, susceptibility_score, telematic_score, has_telematic, stressor_type, why_now, call_by_date, suggested_script, inputs, breakdown) \
+         ON CONFLICT (vehicle_id) DO UPDATE SET upload_id = EXCLUDED.upload_id, urgency_score = EXCLUDED.urgency_score, stressor_score = EXCLUDED.stressor_score, \
+         warranty_score = EXCLUDED.warranty_score, susceptibility_score = EXCLUDED.susceptibility_score, telematic_score = EXCLUDED.telematic_score, \
+         has_telematic = EXCLUDED.has_telematic, stressor_type = EXCLUDED.stressor_type, why_now = EXCLUDED.why_now, \
+         call_by_date = EXCLUDED.call_by_date, suggested_script = EXCLUDED.suggested_script, scoring_version = EXCLUDED.scoring_version, \
+         model_id = EXCLUDED.model_id, inputs = EXCLUDED.inputs, breakdown = EXCLUDED.breakdown, created_at = NOW()"
+    )
+    .bind(SCORING_VERSION)
+    .bind(scored.iter().map(|(v, _)| v.id).collect::<Vec<_>>())
+    .bind(scored.iter().map(|(v, _)| v.upload_id).collect::<Vec<_>>())
+    .bind(scored.iter().map(|(_, s)| s.urgency_score).collect::<Vec<_>>())
+    .bind(scored.iter().map(|(_, s)| s.stressor_score).collect::<Vec<_>>())
+    .bind(scored.iter().map(|(_, s)| s.warranty_score).collect::<Vec<_>>())
+    .bind(scored.iter().map(|(_, s)| s.susceptibility_score).collect::<Vec<_>>())
+    .bind(scored.iter().map(|(_, s)| s.telematic_score).collect::<Vec<_>>())
+    .bind(scored.iter().map(|(_, s)| s.has_telematic).collect::<Vec<_>>())
+    .bind(scored.iter().map(|(_, s)| s.stressor_type.clone()).collect::<Vec<_>>())
+    .bind(scored.iter().map(|(_, s)| s.why_now.clone()).collect::<Vec<_>>())
+    .bind(scored.iter().map(|(_, s)| s.call_by_date).collect::<Vec<_>>())
+    .bind(scored.iter().map(|(_, s)| s.suggested_script.clone()).collect::<Vec<_>>())
+    .bind(model_id)
+    .bind(inputs)
+    .bind(breakdowns)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+struct PendingRowError {
+    row_number: i32,
+    issue: RowIssue,
+    rejected: bool,
+    raw_row: String,
+}
+
+async fn insert_row_errors(
+    upload_id: i32,
+    row_errors: &[PendingRowError],
+    pool: &PgPool,
+) -> anyhow::Result<()> {
+    // Stay well under Postgres' 65535 bind parameter limit
+    for chunk in row_errors.chunks(1000) {
+        let mut builder = QueryBuilder::<Postgres>::new(
+            "INSERT INTO upload_row_errors (upload_id, row_number, column_name, raw_value, reason, rejected, raw_row) "
+        );
+        builder.push_values(chunk, |mut b, e| {
+            b.push_bind(upload_id)
+                .push_bind(e.row_number)
+                .push_bind(&e.issue.column)
+                .push_bind(&e.issue.raw_value)
+                .push_bind(&e.issue.reason)
+                .push_bind(e.rejected)
+                .push_bind(&e.raw_row);
+        });
+        builder.build().execute(pool).await?;
+    }
+    Ok(())
+}
+
+fn encode_csv_record(record: &StringRecord) -> anyhow::Result<String> {
+    let mut writer = WriterBuilder::new().has_headers(false).from_writer(Vec::new());
+    writer.write_record(record)?;
+    let bytes = writer.into_inner().map_err(|e| anyhow::anyhow!("Failed to encode CSV row: {}", e))?;
+    Ok(String::from_utf8_lossy(&bytes).trim_end_matches(['\r', '\n']).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dates::DateOrder;
+    use phonenumber::country::Id as CountryId;
+
+    fn record(vin: &str, customer_phone: &str) -> Result<VehicleInput, String> {
+        Ok(VehicleInput {
+            vin: vin.to_string(),
+            warranty_exp_date: None,
+            customer_name: "Pat Doe".to_string(),
+            customer_phone: customer_phone.to_string(),
+            customer_email: None,
+            customer_zip: None,
+            last_service_date: None,
+        })
+    }
+
+    #[test]
+    fn batch_records_are_validated_as_their_rows() {
+        let settings = ImportSettings {
+            date_order: DateOrder::MonthFirst,
+            default_country: CountryId::US,
+        };
+        let records = [
+            record("1HGCM82633A004352", "202-555-0132"),
+            Err("Invalid record: expected an object".to_string()),
+            record("1HGCM82633A004353", "202-555-0132"),
+            record("1HGCM82633A004352", "(000) 555-0132"),
+        ];
+        let results = validate_batch_records(&records, settings).unwrap();
+        let summary: Vec<(&str, Option<i32>, Vec<&str>)> = results
+            .iter()
+            .map(|r| (r.status.as_str(), r.row_number, r.errors.iter().map(|e| e.reason.as_str()).collect()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("queued", Some(2), vec![]),
+                ("rejected", None, vec!["Invalid record: expected an object"]),
+                ("rejected", Some(3), vec!["VIN check digit is '3', expected '5'"]),
+                (
+                    "rejected",
+                    Some(4),
+                    vec!["Not a valid phone number; kept as typed", "Duplicate VIN; already imported from row 2 of this file"]
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,144 @@
+use crate::models::{DealerQuotas, QuotaUsage};
+use chrono::{Duration, Utc};
+use sqlx::PgPool;
+
+const DEFAULT_ROWS_PER_UPLOAD: i64 = 1_000_000;
+const DEFAULT_UPLOADS_PER_DAY: i64 = 100;
+const DEFAULT_STORED_VEHICLES: i64 = 2_000_000;
+const DEFAULT_CONCURRENT_JOBS: i64 = 1;
+
+/// Limits on how much one dealer can import. Each is the dealer's `dealers.max_*`
+/// column, or the server default when that is NULL.
+#[derive(Debug, Clone, Copy)]
+pub struct DealerLimits {
+    pub rows_per_upload: i64,
+    pub uploads_per_day: i64,
+    pub stored_vehicles: i64,
+    pub concurrent_jobs: i64,
+}
+
+impl DealerLimits {
+    /// Server defaults: `QUOTA_ROWS_PER_UPLOAD` (1,000,000), `QUOTA_UPLOADS_PER_DAY`
+    /// (100), `QUOTA_STORED_VEHICLES` (2,000,000) and `QUOTA_CONCURRENT_JOBS` (1).
+    pub fn defaults() -> Self {
+        DealerLimits {
+            rows_per_upload: env_limit("QUOTA_ROWS_PER_UPLOAD", DEFAULT_ROWS_PER_UPLOAD),
+            uploads_per_day: env_limit("QUOTA_UPLOADS_PER_DAY", DEFAULT_UPLOADS_PER_DAY),
+            stored_vehicles: env_limit("QUOTA_STORED_VEHICLES", DEFAULT_STORED_VEHICLES),
+            concurrent_jobs: env_limit("QUOTA_CONCURRENT_JOBS", DEFAULT_CONCURRENT_JOBS),
+        }
+    }
+
+    pub async fn for_dealer(pool: &PgPool, dealer_id: i32) -> Result<Self, sqlx::Error> {
+        let defaults = DealerLimits::defaults();
+        let (rows_per_upload, uploads_per_day, stored_vehicles, concurrent_jobs): (
+            Option<i32>,
+            Option<i32>,
+            Option<i32>,
+            Option<i32>,
+        ) = sqlx::query_as(
+            "SELECT max_rows_per_upload, max_uploads_per_day, max_stored_vehicles, max_concurrent_jobs FROM dealers WHERE id = $1"
+        )
+        .bind(dealer_id)
+        .fetch_one(pool)
+        .await?;
+
+        Ok(DealerLimits {
+            rows_per_upload: rows_per_upload.map_or(defaults.rows_per_upload, i64::from),
+            uploads_per_day: uploads_per_day.map_or(defaults.uploads_per_day, i64::from),
+            stored_vehicles: stored_vehicles.map_or(defaults.stored_vehicles, i64::from),
+            concurrent_jobs: concurrent_jobs.map_or(defaults.concurrent_jobs, i64::from),
+        })
+    }
+}
+
+fn env_limit(name: &str, default: i64) -> i64 {
+    std::env::var(name)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .filter(|limit| *limit > 0)
+        .unwrap_or(default)
+}
+
+/// Checks whether a dealer may create another upload whose files have the given
+/// `(filename, rows)` counts. Returns the reason when it may not.
+pub async fn check_new_upload(
+    pool: &PgPool,
+    dealer_id: i32,
+    limits: &DealerLimits,
+    file_rows: &[(String, usize)],
+) -> Result<Option<String>, sqlx::Error> {
+    let uploads_today = count_uploads_today(pool, dealer_id).await?;
+    if uploads_today >= limits.uploads_per_day {
+        return Ok(Some(format!(
+            "Daily upload limit reached ({} uploads per day); try again after midnight UTC",
+            limits.uploads_per_day
+        )));
+    }
+
+    if let Some((filename, _)) = file_rows.iter().find(|(_, rows)| *rows as i64 > limits.rows_per_upload) {
+        return Ok(Some(format!(
+            "{} has more than {} rows, the most one upload may contain",
+            filename, limits.rows_per_upload
+        )));
+    }
+
+    let stored = count_stored_vehicles(pool, dealer_id).await?;
+    if stored >= limits.stored_vehicles {
+        return Ok(Some(format!(
+            "Vehicle storage limit reached ({} vehicles); delete old uploads to make room",
+            limits.stored_vehicles
+        )));
+    }
+
+    Ok(None)
+}
+
+/// The dealer's limits with their current usage, for `GET /api/dealers/me`.
+pub async fn dealer_quotas(pool: &PgPool, dealer_id: i32) -> Result<DealerQuotas, sqlx::Error> {
+    let limits = DealerLimits::for_dealer(pool, dealer_id).await?;
+    let running_jobs: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM upload_jobs j JOIN uploads u ON u.id = j.upload_id WHERE u.dealer_id = $1 AND j.status = 'running'"
+    )
+    .bind(dealer_id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(DealerQuotas {
+        rows_per_upload: QuotaUsage {
+            limit: limits.rows_per_upload,
+            used: None,
+        },
+        uploads_per_day: QuotaUsage {
+            limit: limits.uploads_per_day,
+            used: Some(count_uploads_today(pool, dealer_id).await?),
+        },
+        stored_vehicles: QuotaUsage {
+            limit: limits.stored_vehicles,
+            used: Some(count_stored_vehicles(pool, dealer_id).await?),
+        },
+        concurrent_jobs: QuotaUsage {
+            limit: limits.concurrent_jobs,
+            used: Some(running_jobs),
+        },
+        uploads_reset_at: (Utc::now().date_naive() + Duration::days(1)).and_hms_opt(0, 0, 0).unwrap().and_utc(),
+    })
+}
+
+// Uploads since midnight UTC. Files extracted from an archive don't count separately.
+// An archive still `extracting` hasn't been accepted yet, and is counted once it is.
+async fn count_uploads_today(pool: &PgPool, dealer_id: i32) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT COUNT(*) FROM uploads WHERE dealer_id = $1 AND parent_id IS NULL AND status <> 'extracting' AND uploaded_at >= date_trunc('day', NOW() AT TIME ZONE 'UTC') AT TIME ZONE 'UTC'"
+    )
+    .bind(dealer_id)
+    .fetch_one(pool)
+    .await
+}
+
+async fn count_stored_vehicles(pool: &PgPool, dealer_id: i32) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar("SELECT COUNT(*) FROM vehicles WHERE dealer_id = $1")
+        .bind(dealer_id)
+        .fetch_one(pool)
+        .await
+}
@@ -0,0 +1,219 @@
+use crate::charset::{detect_encoding, open_utf8, TextEncoding};
+use calamine::{open_workbook_auto, Data, Reader};
+use csv::{ReaderBuilder, StringRecord};
+use std::io::{Cursor, Read};
+use std::path::Path;
+
+// Delimiters a CSV upload may use, in order of preference when the sample fits several
+const DELIMITERS: [u8; 4] = [b',', b';', b'\t', b'|'];
+// Start of a CSV file examined to choose its delimiter
+const SNIFF_BYTES: u64 = 64 * 1024;
+const SNIFF_LINES: usize = 20;
+
+/// Upload file formats we know how to read, detected from the file extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileFormat {
+    Csv,
+    Excel,
+}
+
+impl FileFormat {
+    pub fn from_path(path: &str) -> Option<Self> {
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase())?;
+        match extension.as_str() {
+            "csv" | "txt" => Some(FileFormat::Csv),
+            "xlsx" | "xlsm" | "xls" => Some(FileFormat::Excel),
+            _ => None,
+        }
+    }
+}
+
+/// A data row together with its 1-based row number in the source file
+/// (the header is row 1), as a person would see it in a spreadsheet.
+pub struct SourceRow {
+    pub row_number: i32,
+    pub record: StringRecord,
+}
+
+pub type SourceRows = Box<dyn Iterator<Item = anyhow::Result<SourceRow>> + Send>;
+
+/// How a CSV upload's text is written, detected when the file is opened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextFormat {
+    pub encoding: TextEncoding,
+    pub delimiter: u8,
+}
+
+impl TextFormat {
+    /// The delimiter as stored in `uploads.delimiter`.
+    pub fn delimiter_name(&self) -> &'static str {
+        match self.delimiter {
+            b';' => "semicolon",
+            b'\t' => "tab",
+            b'|' => "pipe",
+            _ => "comma",
+        }
+    }
+}
+
+/// Opens an upload file and returns its header row, an iterator over its data rows
+/// and, for CSV files, the detected encoding and delimiter. `sheet` selects an Excel
+/// worksheet by name; the first sheet is used when it is `None`.
+pub fn open_records(path: &str, sheet: Option<&str>) -> anyhow::Result<(StringRecord, SourceRows, Option<TextFormat>)> {
+    match FileFormat::from_path(path) {
+        Some(FileFormat::Excel) => {
+            let (headers, rows) = open_excel(path, sheet)?;
+            Ok((headers, rows, None))
+        }
+        Some(FileFormat::Csv) | None => {
+            let (headers, rows, format) = open_csv(path)?;
+            Ok((headers, rows, Some(format)))
+        }
+    }
+}
+
+/// Counts the data rows of an upload file, stopping once there are more than `limit`.
+pub fn count_rows(path: &str, sheet: Option<&str>, limit: usize) -> anyhow::Result<usize> {
+    let (_, rows, _) = open_records(path, sheet)?;
+    Ok(rows.take(limit.saturating_add(1)).count())
+}
+
+// The file is transcoded to UTF-8 as it's read; the delimiter is chosen from its start.
+fn open_csv(path: &str) -> anyhow::Result<(StringRecord, SourceRows, TextFormat)> {
+    let encoding = detect_encoding(Path::new(path))?;
+    let mut text = open_utf8(Path::new(path), encoding)?;
+    let mut sample = Vec::new();
+    (&mut text).take(SNIFF_BYTES).read_to_end(&mut sample)?;
+    let format = TextFormat {
+        encoding,
+        delimiter: sniff_delimiter(&sample),
+    };
+
+    let mut reader = ReaderBuilder::new()
+        .has_headers(true)
+        .flexible(true)
+        .delimiter(format.delimiter)
+        .from_reader(Cursor::new(sample).chain(text));
+    let headers = reader.headers()?.clone();
+
+    // With CRLF line endings the reader reports each record's line before counting
+    // the previous record's trailing "\n", so every line number lags by one. The
+    // first data row always starts on line 2 or later, which reveals the lag.
+    let mut fallback_row = 1;
+    let mut line_offset = None;
+    let rows = reader.into_records().map(move |result| {
+        let record = result?;
+        fallback_row += 1;
+        let row_number = match record.position() {
+            Some(position) => {
+                let line = position.line() as i32;
+                let offset = *line_offset.get_or_insert(if line < 2 { 2 - line } else { 0 });
+                line + offset
+            }
+            None => fallback_row,
+        };
+        Ok(SourceRow { row_number, record })
+    });
+
+    Ok((headers, Box::new(rows), format))
+}
+
+// Picks the delimiter that splits the most sample lines into as many fields as the
+// header row, then the one giving the most header fields. Comma is the fallback.
+fn sniff_delimiter(sample: &[u8]) -> u8 {
+    let text = String::from_utf8_lossy(sample);
+    let mut lines: Vec<&str> = text.lines().filter(|line| !line.trim().is_empty()).collect();
+    if sample.len() as u64 >= SNIFF_BYTES {
+        // The last line may be cut off
+        lines.pop();
+    }
+    lines.truncate(SNIFF_LINES);
+    let Some(header) = lines.first() else {
+        return b',';
+    };
+
+    DELIMITERS
+        .iter()
+        .enumerate()
+        .filter_map(|(priority, &delimiter)| {
+            let fields = count_unquoted(header, delimiter);
+            let consistent = lines.iter().filter(|line| count_unquoted(line, delimiter) == fields).count();
+            (fields > 0).then_some((consistent, fields, std::cmp::Reverse(priority), delimiter))
+        })
+        .max()
+        .map(|(_, _, _, delimiter)| delimiter)
+        .unwrap_or(b',')
+}
+
+// Occurrences of `delimiter` in a line outside double-quoted values
+fn count_unquoted(line: &str, delimiter: u8) -> usize {
+    let mut quoted = false;
+    line.bytes()
+        .filter(|&b| {
+            if b == b'"' {
+                quoted = !quoted;
+            }
+            !quoted && b == delimiter
+        })
+        .count()
+}
+
+fn open_excel(path: &str, sheet: Option<&str>) -> anyhow::Result<(StringRecord, SourceRows)> {
+    let mut workbook = open_workbook_auto(path)?;
+    let sheet_names = workbook.sheet_names();
+    let sheet_name = match sheet {
+        Some(name) => sheet_names
+            .iter()
+            .find(|s| s.eq_ignore_ascii_case(name.trim()))
+            .cloned()
+            .ok_or_else(|| {
+                anyhow::anyhow!("Sheet '{}' not found. Available sheets: {}", name, sheet_names.join(", "))
+            })?,
+        None => sheet_names
+            .first()
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("Workbook has no sheets"))?,
+    };
+
+    let range = workbook.worksheet_range(&sheet_name)?;
+    let first_row = range.start().map(|(row, _)| row as i32 + 1).unwrap_or(1);
+    let mut rows = range.rows().map(|cells| cells.iter().map(cell_to_string).collect::<StringRecord>());
+
+    let headers = rows
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("Sheet '{}' is empty", sheet_name))?;
+    let records: Vec<anyhow::Result<SourceRow>> = rows
+        .enumerate()
+        .filter(|(_, record)| record.iter().any(|field| !field.is_empty()))
+        .map(|(i, record)| {
+            Ok(SourceRow {
+                row_number: first_row + 1 + i as i32,
+                record,
+            })
+        })
+        .collect();
+
+    Ok((headers, Box::new(records.into_iter())))
+}
+
+// Dates become YYYY-MM-DD and whole numbers lose their ".0", so phone numbers
+// and zip codes stored as numbers come through the way they were typed.
+fn cell_to_string(cell: &Data) -> String {
+    match cell {
+        Data::Empty | Data::Error(_) => String::new(),
+        Data::String(s) => s.trim().to_string(),
+        Data::Int(i) => i.to_string(),
+        Data::Float(f) if f.fract() == 0.0 && f.abs() < 1e15 => format!("{}", *f as i64),
+        Data::Float(f) => f.to_string(),
+        Data::Bool(b) => b.to_string(),
+        Data::DateTime(dt) => dt
+            .as_datetime()
+            .map(|d| d.date().format("%Y-%m-%d").to_string())
+            .unwrap_or_else(|| dt.as_f64().to_string()),
+        Data::DateTimeIso(s) => s.get(..10).unwrap_or(s).to_string(),
+        Data::DurationIso(s) => s.clone(),
+    }
+}
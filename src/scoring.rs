@@ -1,5 +1,28 @@
-use crate::models::Vehicle;
-use chrono::{NaiveDate, Utc};
+use crate::models::{TelematicsReading, Vehicle};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::PgPool;
+
+/// Identifies the scoring rules below; stored with every lead they produce.
+/// Bump it whenever a change would score the same vehicle differently.
+pub const SCORING_VERSION: &str = "4";
+
+/// Id of the strategy that scores with `calculate_lead_scores`.
+pub const DEFAULT_STRATEGY: &str = "weighted";
+
+// Telematic readings at or below these raise an alert
+const LOW_OIL_LIFE_PERCENT: f32 = 15.0;
+const LOW_BATTERY_VOLTS: f32 = 12.0;
+// Days until the call for a lead whose vehicle reports an active trouble code or low oil
+const ALERT_CALL_DAYS: i64 = 1;
+// Factors scoring at least this give the suggested script a talking point
+const TALKING_POINT_SCORE: f64 = 0.5;
+const MAX_TALKING_POINTS: usize = 2;
+
+// Upper bounds for a dealer's configuration
+const MAX_CALL_DAYS: i64 = 365;
+const MAX_CALL_WINDOWS: usize = 10;
 
 pub struct LeadScores {
     pub urgency_score: f32,
@@ -12,44 +35,365 @@ pub struct LeadScores {
     pub why_now: String,
     pub call_by_date: NaiveDate,
     pub suggested_script: String,
+    pub inputs: ScoringInputs,
+    pub breakdown: ScoreBreakdown,
 }
 
-pub fn calculate_lead_scores(vehicle: &Vehicle) -> LeadScores {
-    let today = Utc::now().date_naive();
-    
-    // Warranty score: Higher if warranty is expiring soon (within 90 days)
-    let warranty_score = vehicle.warranty_exp_date
-        .map(|exp_date| {
-            let days_until_expiry = (exp_date - today).num_days();
-            if days_until_expiry < 0 {
-                0.0 // Warranty already expired
-            } else if days_until_expiry <= 30 {
-                1.0 // Expiring very soon
-            } else if days_until_expiry <= 60 {
-                0.8
-            } else if days_until_expiry <= 90 {
-                0.6
-            } else {
-                0.3 // Still has time
+/// How each factor contributed to a lead's urgency score, stored with it as
+/// `scored_leads.breakdown`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ScoreBreakdown {
+    pub warranty: FactorScore,
+    pub service: FactorScore,
+    pub stressor: FactorScore,
+    pub susceptibility: FactorScore,
+    pub telematic: FactorScore,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FactorScore {
+    /// The raw value(s) the factor was scored from
+    pub input: serde_json::Value,
+    /// Which of the factor's score buckets the input fell in
+    pub bucket: String,
+    pub score: f64,
+    pub weight: f64,
+    /// `score * weight`; the urgency score is the sum of these, capped at 1.0
+    pub contribution: f64,
+}
+
+impl FactorScore {
+    fn new(input: serde_json::Value, bucket: String, score: f32, weight: f32) -> Self {
+        FactorScore {
+            input,
+            bucket,
+            score: json_number(score),
+            weight: json_number(weight),
+            contribution: json_number(score * weight),
+        }
+    }
+}
+
+// JSON numbers are f64, so a score is widened from its shortest decimal form: 0.9_f32
+// becomes 0.9 rather than 0.8999999761581421.
+fn json_number(value: f32) -> f64 {
+    value.to_string().parse().unwrap_or(value as f64)
+}
+
+/// The values a lead was scored from, stored with it as `scored_leads.inputs` so
+/// its scores can be explained later.
+#[derive(Debug, Clone, Serialize)]
+pub struct ScoringInputs {
+    pub as_of: NaiveDate,
+    pub warranty_exp_date: Option<NaiveDate>,
+    pub last_service_date: Option<NaiveDate>,
+    pub contact_status: Option<String>,
+    pub has_email: bool,
+    pub has_zip: bool,
+    pub customer_name: String,
+    pub telematics: Option<TelematicsReading>,
+}
+
+impl ScoringInputs {
+    pub fn new(vehicle: &Vehicle, telematics: Option<&TelematicsReading>, as_of: NaiveDate) -> Self {
+        ScoringInputs {
+            as_of,
+            warranty_exp_date: vehicle.warranty_exp_date,
+            last_service_date: vehicle.last_service_date,
+            contact_status: vehicle.contact_status.clone(),
+            has_email: vehicle.customer_email.is_some(),
+            has_zip: vehicle.customer_zip.is_some(),
+            customer_name: vehicle.customer_name.clone(),
+            telematics: telematics.cloned(),
+        }
+    }
+}
+
+/// Turns a vehicle into a lead. Each dealer's uploads are scored by the strategy
+/// named in its scoring settings, configured with the settings' `config`.
+pub trait ScoringStrategy: Send + Sync {
+    /// Stable id stored in `dealer_scoring_settings.strategy`
+    fn id(&self) -> &'static str;
+
+    /// Scores a vehicle, with its latest recent telematics reading if it has one, as it
+    /// stands on `as_of`; the same inputs always give the same lead.
+    fn score(&self, vehicle: &Vehicle, telematics: Option<&TelematicsReading>, as_of: NaiveDate) -> LeadScores;
+}
+
+/// Ids of every strategy, for error messages.
+pub const STRATEGIES: [&str; 1] = [DEFAULT_STRATEGY];
+
+pub fn strategy_by_id(id: &str, config: ScoringConfig) -> Option<Box<dyn ScoringStrategy>> {
+    match id.trim().to_ascii_lowercase().as_str() {
+        DEFAULT_STRATEGY => Some(Box::new(WeightedScoring { config })),
+        _ => None,
+    }
+}
+
+/// Scores each factor by the thresholds of its config and combines them with its weights.
+pub struct WeightedScoring {
+    pub config: ScoringConfig,
+}
+
+impl ScoringStrategy for WeightedScoring {
+    fn id(&self) -> &'static str {
+        DEFAULT_STRATEGY
+    }
+
+    fn score(&self, vehicle: &Vehicle, telematics: Option<&TelematicsReading>, as_of: NaiveDate) -> LeadScores {
+        calculate_lead_scores(vehicle, telematics, &self.config, as_of)
+    }
+}
+
+/// The weights, day thresholds and call windows used by `calculate_lead_scores`.
+/// Fields left out of a dealer's settings keep these defaults.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScoringConfig {
+    pub weights: ScoringWeights,
+    /// Warranties expiring within these many days score 1.0, 0.8 and 0.6; later ones score 0.3
+    pub warranty_days: [i64; 3],
+    /// Services more than these many days ago score 0.5, 0.7 and 0.9; more recent ones score 0.2
+    pub service_days: [i64; 3],
+    /// Leads are called within the `days` of the first window whose urgency they exceed
+    pub call_windows: Vec<CallWindow>,
+    /// Days until the call for leads below every window
+    pub default_call_days: i64,
+}
+
+/// Weight of each factor in the urgency score, which is capped at 1.0.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScoringWeights {
+    pub warranty: f32,
+    pub service: f32,
+    pub stressor: f32,
+    pub susceptibility: f32,
+    pub telematic: f32,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CallWindow {
+    pub above_urgency: f32,
+    pub days: i64,
+}
+
+impl Default for ScoringConfig {
+    fn default() -> Self {
+        ScoringConfig {
+            weights: ScoringWeights::default(),
+            warranty_days: [30, 60, 90],
+            service_days: [90, 180, 365],
+            call_windows: vec![
+                CallWindow { above_urgency: 0.8, days: 1 },
+                CallWindow { above_urgency: 0.6, days: 3 },
+                CallWindow { above_urgency: 0.4, days: 7 },
+            ],
+            default_call_days: 14,
+        }
+    }
+}
+
+impl Default for ScoringWeights {
+    fn default() -> Self {
+        ScoringWeights {
+            warranty: 0.3,
+            service: 0.3,
+            stressor: 0.2,
+            susceptibility: 0.1,
+            telematic: 0.1,
+        }
+    }
+}
+
+impl ScoringConfig {
+    /// Checks a configuration before it is saved, returning the first problem found.
+    pub fn validate(&self) -> Result<(), String> {
+        let w = &self.weights;
+        let weights = [w.warranty, w.service, w.stressor, w.susceptibility, w.telematic];
+        if weights.iter().any(|w| !w.is_finite() || *w < 0.0) {
+            return Err("Weights must be zero or more".to_string());
+        }
+        if weights.iter().all(|w| *w == 0.0) {
+            return Err("At least one weight must be above zero".to_string());
+        }
+
+        for (name, days) in [("warranty_days", &self.warranty_days), ("service_days", &self.service_days)] {
+            if days[0] < 0 || !days.windows(2).all(|pair| pair[0] < pair[1]) {
+                return Err(format!("{} must be three increasing numbers of days, starting at 0 or more", name));
             }
+        }
+
+        if self.call_windows.len() > MAX_CALL_WINDOWS {
+            return Err(format!("At most {} call windows are allowed", MAX_CALL_WINDOWS));
+        }
+        if self
+            .call_windows
+            .iter()
+            .any(|window| !(0.0..1.0).contains(&window.above_urgency))
+        {
+            return Err("Each call window's above_urgency must be at least 0 and below 1".to_string());
+        }
+        if !self
+            .call_windows
+            .windows(2)
+            .all(|pair| pair[0].above_urgency > pair[1].above_urgency)
+        {
+            return Err("Call windows must be listed from the highest above_urgency to the lowest".to_string());
+        }
+        let mut days = self.call_windows.iter().map(|window| window.days).chain([self.default_call_days]);
+        if days.any(|days| !(0..=MAX_CALL_DAYS).contains(&days)) {
+            return Err(format!("Call window days must be between 0 and {}", MAX_CALL_DAYS));
+        }
+        Ok(())
+    }
+
+    // Days from the scoring date until a lead of this urgency should be called
+    fn call_days(&self, urgency_score: f32) -> i64 {
+        self.call_windows
+            .iter()
+            .find(|window| urgency_score > window.above_urgency)
+            .map_or(self.default_call_days, |window| window.days)
+    }
+}
+
+/// A dealer's scoring strategy and configuration, from `dealer_scoring_settings`.
+/// Dealers without a row are scored with the default strategy and config.
+#[derive(Debug, Clone, Serialize)]
+pub struct ScoringSettings {
+    pub strategy: String,
+    pub config: ScoringConfig,
+    /// When the dealer last changed its settings; `None` while it uses the defaults
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl Default for ScoringSettings {
+    fn default() -> Self {
+        ScoringSettings {
+            strategy: DEFAULT_STRATEGY.to_string(),
+            config: ScoringConfig::default(),
+            updated_at: None,
+        }
+    }
+}
+
+impl ScoringSettings {
+    pub async fn for_dealer(pool: &PgPool, dealer_id: i32) -> Result<Self, sqlx::Error> {
+        let row: Option<(String, sqlx::types::Json<ScoringConfig>, DateTime<Utc>)> =
+            sqlx::query_as("SELECT strategy, config, updated_at FROM dealer_scoring_settings WHERE dealer_id = $1")
+                .bind(dealer_id)
+                .fetch_optional(pool)
+                .await?;
+
+        Ok(row
+            .map(|(strategy, config, updated_at)| ScoringSettings {
+                strategy,
+                config: config.0,
+                updated_at: Some(updated_at),
+            })
+            .unwrap_or_default())
+    }
+
+    /// The strategy these settings select, configured with their `config`.
+    pub fn strategy(&self) -> anyhow::Result<Box<dyn ScoringStrategy>> {
+        strategy_by_id(&self.strategy, self.config.clone())
+            .ok_or_else(|| anyhow::anyhow!("Unknown scoring strategy: {}", self.strategy))
+    }
+
+    /// Id of the scoring model for these settings under the current `SCORING_VERSION`,
+    /// registering it in `scoring_models` the first time it is used.
+    pub async fn register_model(&self, pool: &PgPool) -> Result<i32, sqlx::Error> {
+        sqlx::query_scalar(
+            "INSERT INTO scoring_models (scoring_version, strategy, config) VALUES ($1, $2, $3) \
+             ON CONFLICT (scoring_version, strategy, config) DO UPDATE SET strategy = EXCLUDED.strategy RETURNING id"
+        )
+        .bind(SCORING_VERSION)
+        .bind(&self.strategy)
+        .bind(sqlx::types::Json(&self.config))
+        .fetch_one(pool)
+        .await
+    }
+
+    /// Id of the scoring model for these settings, if anything has been scored with it.
+    pub async fn find_model(&self, pool: &PgPool) -> Result<Option<i32>, sqlx::Error> {
+        sqlx::query_scalar("SELECT id FROM scoring_models WHERE scoring_version = $1 AND strategy = $2 AND config = $3")
+            .bind(SCORING_VERSION)
+            .bind(&self.strategy)
+            .bind(sqlx::types::Json(&self.config))
+            .fetch_optional(pool)
+            .await
+    }
+}
+
+/// A dealer's scoring strategy and the model it is registered as, scoring
+/// vehicles as of one date.
+pub struct LeadScorer {
+    /// `None` until the model has scored a stored lead
+    pub model_id: Option<i32>,
+    pub as_of: NaiveDate,
+    strategy: Box<dyn ScoringStrategy>,
+}
+
+impl LeadScorer {
+    /// The dealer's scorer, with its model looked up but never registered, for
+    /// scores that aren't stored.
+    pub async fn for_dealer(pool: &PgPool, dealer_id: i32, as_of: NaiveDate) -> anyhow::Result<Self> {
+        let settings = ScoringSettings::for_dealer(pool, dealer_id).await?;
+        Ok(LeadScorer {
+            model_id: settings.find_model(pool).await?,
+            as_of,
+            strategy: settings.strategy()?,
         })
-        .unwrap_or(0.0);
+    }
 
-    // Last service date score: Higher if service was long ago
-    let service_score = vehicle.last_service_date
-        .map(|last_service| {
-            let days_since_service = (today - last_service).num_days();
-            if days_since_service > 365 {
-                0.9 // Over a year since service
-            } else if days_since_service > 180 {
-                0.7
-            } else if days_since_service > 90 {
-                0.5
-            } else {
-                0.2 // Recently serviced
-            }
+    /// The dealer's scorer with its model registered, for leads that will be stored.
+    pub async fn registered(pool: &PgPool, dealer_id: i32, as_of: NaiveDate) -> anyhow::Result<Self> {
+        let settings = ScoringSettings::for_dealer(pool, dealer_id).await?;
+        Ok(LeadScorer {
+            model_id: Some(settings.register_model(pool).await?),
+            as_of,
+            strategy: settings.strategy()?,
         })
-        .unwrap_or(0.8); // No service record = high score
+    }
+
+    pub fn score(&self, vehicle: &Vehicle, telematics: Option<&TelematicsReading>) -> LeadScores {
+        self.strategy.score(vehicle, telematics, self.as_of)
+    }
+}
+
+/// Scores a vehicle as of the given date: days until warranty expiry, days since
+/// service and the call-by date are all counted from `as_of`. `telematics` is the
+/// vehicle's latest reading from the days before `as_of`, if any.
+pub fn calculate_lead_scores(
+    vehicle: &Vehicle,
+    telematics: Option<&TelematicsReading>,
+    config: &ScoringConfig,
+    as_of: NaiveDate,
+) -> LeadScores {
+    let [warranty_soon, warranty_near, warranty_window] = config.warranty_days;
+    let [service_due, service_late, service_overdue] = config.service_days;
+    
+    // Warranty score: Higher if warranty is expiring soon (within 90 days by default)
+    let days_until_expiry = vehicle.warranty_exp_date.map(|exp_date| (exp_date - as_of).num_days());
+    let (warranty_score, warranty_bucket) = match days_until_expiry {
+        Some(days) if days < 0 => (0.0, "expired".to_string()), // Warranty already expired
+        Some(days) if days <= warranty_soon => (1.0, format!("expires within {} days", warranty_soon)), // Expiring very soon
+        Some(days) if days <= warranty_near => (0.8, format!("expires within {} days", warranty_near)),
+        Some(days) if days <= warranty_window => (0.6, format!("expires within {} days", warranty_window)),
+        Some(_) => (0.3, format!("expires in more than {} days", warranty_window)), // Still has time
+        None => (0.0, "no warranty date".to_string()),
+    };
+
+    // Last service date score: Higher if service was long ago
+    let days_since_service = vehicle.last_service_date.map(|last_service| (as_of - last_service).num_days());
+    let (service_score, service_bucket) = match days_since_service {
+        Some(days) if days > service_overdue => (0.9, format!("more than {} days ago", service_overdue)), // Over a year since service
+        Some(days) if days > service_late => (0.7, format!("more than {} days ago", service_late)),
+        Some(days) if days > service_due => (0.5, format!("more than {} days ago", service_due)),
+        Some(_) => (0.2, format!("within {} days", service_due)), // Recently serviced
+        None => (0.8, "no service record".to_string()), // No service record = high score
+    };
 
     // Stressor score: Combination of warranty and service
     let stressor_score = (warranty_score * 0.6_f32 + service_score * 0.4_f32).min(1.0_f32);
@@ -65,45 +409,95 @@ pub fn calculate_lead_scores(vehicle: &Vehicle) -> LeadScores {
         Some("Maintenance Reminder".to_string())
     };
 
-    // Susceptibility score: Based on customer data completeness
-    let susceptibility_score = if vehicle.customer_email.is_some() && vehicle.customer_zip.is_some() {
-        0.8 // Good contact info
-    } else if vehicle.customer_email.is_some() || vehicle.customer_zip.is_some() {
-        0.5
-    } else {
-        0.3
+    // Susceptibility score: Based on whether the customer can actually be reached.
+    // Stored emails have already been validated; vehicles imported before phones
+    // were validated have no contact status and are scored on completeness.
+    let has_email = vehicle.customer_email.is_some();
+    let has_zip = vehicle.customer_zip.is_some();
+    let (susceptibility_score, susceptibility_bucket) = match vehicle.contact_status.as_deref() {
+        Some("valid") => (
+            0.5 + if has_email { 0.2 } else { 0.0 } + if has_zip { 0.1 } else { 0.0 },
+            match (has_email, has_zip) {
+                (true, true) => "valid phone, email and zip",
+                (true, false) => "valid phone and email",
+                (false, true) => "valid phone and zip",
+                (false, false) => "valid phone only",
+            },
+        ),
+        Some(_) if has_email => (0.3, "email only"), // Email only
+        Some(_) => (0.0, "unreachable"), // No way to reach the customer
+        None if has_email && has_zip => (0.8, "unchecked phone, email and zip"),
+        None if has_email || has_zip => (0.5, "unchecked phone and email or zip"),
+        None => (0.3, "unchecked phone only"),
     };
 
-    // Telematic score: Placeholder (would check actual telematic data)
-    let has_telematic = false; // Would check actual data
-    let telematic_score = if has_telematic { 0.9 } else { 0.1 };
+    // Telematic score: Highest when the vehicle reports a fault. An active trouble
+    // code or low oil life is an alert, which brings the call forward to tomorrow.
+    let has_telematic = telematics.is_some();
+    let (telematic_score, telematic_bucket, telematic_alert) = match telematics {
+        None => (0.1, "no recent telematic data", false),
+        Some(reading) if !reading.dtc_codes.is_empty() => (1.0, "active trouble codes", true),
+        Some(reading) if reading.oil_life_percent.is_some_and(|oil| oil <= LOW_OIL_LIFE_PERCENT) => {
+            (0.9, "low oil life", true)
+        }
+        Some(reading) if reading.battery_voltage.is_some_and(|volts| volts < LOW_BATTERY_VOLTS) => {
+            (0.6, "low battery voltage", false)
+        }
+        Some(_) => (0.2, "readings normal", false),
+    };
 
     // Urgency score: Weighted combination
+    let weights = &config.weights;
     let urgency_score = (
-        warranty_score * 0.3_f32 +
-        service_score * 0.3_f32 +
-        stressor_score * 0.2_f32 +
-        susceptibility_score * 0.1_f32 +
-        telematic_score * 0.1_f32
+        warranty_score * weights.warranty +
+        service_score * weights.service +
+        stressor_score * weights.stressor +
+        susceptibility_score * weights.susceptibility +
+        telematic_score * weights.telematic
     ).min(1.0_f32);
+    let breakdown = ScoreBreakdown {
+        warranty: FactorScore::new(json!({ "days_until_expiry": days_until_expiry }), warranty_bucket, warranty_score, weights.warranty),
+        service: FactorScore::new(json!({ "days_since_service": days_since_service }), service_bucket, service_score, weights.service),
+        stressor: FactorScore::new(
+            json!({ "warranty_score": json_number(warranty_score), "service_score": json_number(service_score) }),
+            stressor_type.clone().unwrap_or_default(),
+            stressor_score,
+            weights.stressor,
+        ),
+        susceptibility: FactorScore::new(
+            json!({ "contact_status": vehicle.contact_status, "has_email": has_email, "has_zip": has_zip }),
+            susceptibility_bucket.to_string(),
+            susceptibility_score,
+            weights.susceptibility,
+        ),
+        telematic: FactorScore::new(
+            json!({
+                "has_telematic": has_telematic,
+                "recorded_at": telematics.map(|r| r.recorded_at),
+                "dtc_codes": telematics.map(|r| &r.dtc_codes),
+                "oil_life_percent": telematics.and_then(|r| r.oil_life_percent).map(json_number),
+                "battery_voltage": telematics.and_then(|r| r.battery_voltage).map(json_number),
+                "odometer_miles": telematics.and_then(|r| r.odometer_miles),
+            }),
+            telematic_bucket.to_string(),
+            telematic_score,
+            weights.telematic,
+        ),
+    };
 
     // Calculate call_by_date: More urgent = sooner call date
-    let days_until_call = if urgency_score > 0.8 {
-        1 // Call tomorrow
-    } else if urgency_score > 0.6 {
-        3
-    } else if urgency_score > 0.4 {
-        7
+    let days_until_call = if telematic_alert {
+        config.call_days(urgency_score).min(ALERT_CALL_DAYS)
     } else {
-        14
+        config.call_days(urgency_score)
     };
-    let call_by_date = today + chrono::Duration::days(days_until_call);
+    let call_by_date = as_of + chrono::Duration::days(days_until_call);
 
     // Generate why_now message
-    let why_now = generate_why_now(&vehicle, warranty_score, service_score, stressor_type.as_deref());
+    let why_now = generate_why_now(vehicle, telematics, warranty_score, service_score, stressor_type.as_deref(), as_of);
     
     // Generate suggested script
-    let suggested_script = generate_script(&vehicle, stressor_type.as_deref(), warranty_score, service_score);
+    let suggested_script = generate_script(vehicle, telematics, &breakdown, days_until_expiry, days_since_service);
 
     LeadScores {
         urgency_score,
@@ -116,15 +510,36 @@ pub fn calculate_lead_scores(vehicle: &Vehicle) -> LeadScores {
         why_now,
         call_by_date,
         suggested_script,
+        inputs: ScoringInputs::new(vehicle, telematics, as_of),
+        breakdown,
     }
 }
 
-fn generate_why_now(vehicle: &Vehicle, warranty_score: f32, service_score: f32, _stressor_type: Option<&str>) -> String {
+fn generate_why_now(
+    vehicle: &Vehicle,
+    telematics: Option<&TelematicsReading>,
+    warranty_score: f32,
+    service_score: f32,
+    _stressor_type: Option<&str>,
+    as_of: NaiveDate,
+) -> String {
     let mut reasons = Vec::new();
+
+    if let Some(reading) = telematics {
+        if !reading.dtc_codes.is_empty() {
+            reasons.push(format!("Vehicle reports trouble code(s) {}", reading.dtc_codes.join(", ")));
+        }
+        if let Some(oil) = reading.oil_life_percent.filter(|oil| *oil <= LOW_OIL_LIFE_PERCENT) {
+            reasons.push(format!("Oil life is down to {}%", oil));
+        }
+        if let Some(volts) = reading.battery_voltage.filter(|volts| *volts < LOW_BATTERY_VOLTS) {
+            reasons.push(format!("Battery voltage is low ({} V)", volts));
+        }
+    }
     
     if warranty_score > 0.7 {
         if let Some(exp_date) = vehicle.warranty_exp_date {
-            let days = (exp_date - Utc::now().date_naive()).num_days();
+            let days = (exp_date - as_of).num_days();
             if days > 0 {
                 reasons.push(format!("Warranty expires in {} days", days));
             } else {
@@ -135,7 +550,7 @@ fn generate_why_now(vehicle: &Vehicle, warranty_score: f32, service_score: f32,
     
     if service_score > 0.7 {
         if let Some(last_service) = vehicle.last_service_date {
-            let days = (Utc::now().date_naive() - last_service).num_days();
+            let days = (as_of - last_service).num_days();
             reasons.push(format!("Last service was {} days ago", days));
         } else {
             reasons.push("No service record found".to_string());
@@ -149,15 +564,367 @@ fn generate_why_now(vehicle: &Vehicle, warranty_score: f32, service_score: f32,
     format!("Customer should be contacted because: {}. This is an optimal time to reach out and provide value.", reasons.join(", "))
 }
 
-fn generate_script(vehicle: &Vehicle, stressor_type: Option<&str>, _warranty_score: f32, _service_score: f32) -> String {
-    let customer_name = &vehicle.customer_name;
-    let stressor = stressor_type.unwrap_or("maintenance");
-    
+// Opens with the factors that did most to raise the lead's urgency, so the script
+// agrees with its breakdown: each factor scoring at least `TALKING_POINT_SCORE`, by
+// contribution. The stressor factor only restates warranty and service, and reach
+// isn't something to raise with the customer, so neither gives a talking point.
+fn generate_script(
+    vehicle: &Vehicle,
+    telematics: Option<&TelematicsReading>,
+    breakdown: &ScoreBreakdown,
+    days_until_expiry: Option<i64>,
+    days_since_service: Option<i64>,
+) -> String {
+    let telematic_point = telematics.and_then(|reading| {
+        if !reading.dtc_codes.is_empty() {
+            let plural = if reading.dtc_codes.len() > 1 { "s" } else { "" };
+            Some(format!("your vehicle is reporting trouble code{} {}", plural, reading.dtc_codes.join(", ")))
+        } else if let Some(oil) = reading.oil_life_percent.filter(|oil| *oil <= LOW_OIL_LIFE_PERCENT) {
+            Some(format!("your vehicle's oil life is down to {}%", oil))
+        } else if reading.battery_voltage.is_some_and(|volts| volts < LOW_BATTERY_VOLTS) {
+            Some("your vehicle's battery is running low".to_string())
+        } else {
+            None
+        }
+    });
+    let warranty_point = days_until_expiry.filter(|days| *days >= 0).map(|days| match days {
+        0 => "your factory warranty ends today".to_string(),
+        1 => "your factory warranty ends tomorrow".to_string(),
+        _ => format!("your factory warranty ends in {} days", days),
+    });
+    let service_point = Some(match days_since_service {
+        Some(days) => format!("it has been {} days since your last service with us", days),
+        None => "we don't have a service visit on file for your vehicle".to_string(),
+    });
+
+    let mut points: Vec<(f64, String)> = [
+        (&breakdown.telematic, telematic_point),
+        (&breakdown.warranty, warranty_point),
+        (&breakdown.service, service_point),
+    ]
+    .into_iter()
+    .filter(|(factor, _)| factor.score >= TALKING_POINT_SCORE && factor.contribution > 0.0)
+    .filter_map(|(factor, point)| Some((factor.contribution, point?)))
+    .collect();
+    // Stable, so equal contributions keep the order above
+    points.sort_by(|a, b| b.0.total_cmp(&a.0));
+    let points: Vec<String> = points.into_iter().take(MAX_TALKING_POINTS).map(|(_, point)| point).collect();
+    let reason = if points.is_empty() {
+        "your vehicle is due for some routine maintenance".to_string()
+    } else {
+        points.join(", and ")
+    };
+
     format!(
-        "Hi {}, this is [Your Name] from [Dealership]. I wanted to reach out because your vehicle's {} is coming up. \
+        "Hi {}, this is [Your Name] from [Dealership]. I wanted to reach out because {}. \
         We'd love to help ensure your vehicle stays in great condition. Would you be available for a quick conversation \
         about scheduling a service appointment? We can work around your schedule and make sure everything is taken care of.",
-        customer_name, stressor
+        vehicle.customer_name, reason
     )
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn as_of() -> NaiveDate {
+        date(2026, 3, 1)
+    }
+
+    fn days(n: i64) -> NaiveDate {
+        as_of() + chrono::Duration::days(n)
+    }
+
+    fn vehicle(warranty_exp_date: Option<NaiveDate>, last_service_date: Option<NaiveDate>) -> Vehicle {
+        Vehicle {
+            id: 1,
+            upload_id: 1,
+            dealer_id: 1,
+            vin: "1HGCM82633A004352".to_string(),
+            wmi: None,
+            manufacturer: None,
+            model_year: None,
+            plant_code: None,
+            warranty_exp_date,
+            customer_name: "Pat Doe".to_string(),
+            customer_phone: "+15551234567".to_string(),
+            customer_phone_ext: None,
+            customer_email: Some("pat@example.com".to_string()),
+            customer_zip: Some("90210".to_string()),
+            last_service_date,
+            contact_status: Some("valid".to_string()),
+            created_at: Utc::now(),
+        }
+    }
+
+    fn reading(dtc_codes: &[&str], battery_voltage: Option<f32>, oil_life_percent: Option<f32>) -> TelematicsReading {
+        TelematicsReading {
+            vin: "1HGCM82633A004352".to_string(),
+            recorded_at: Utc::now(),
+            odometer_miles: Some(42_000),
+            dtc_codes: dtc_codes.iter().map(|code| code.to_string()).collect(),
+            battery_voltage,
+            oil_life_percent,
+        }
+    }
+
+    // Only the named factor counts, so the urgency score is that factor's score
+    fn weighted_only(warranty: f32, service: f32) -> ScoringConfig {
+        ScoringConfig {
+            weights: ScoringWeights {
+                warranty,
+                service,
+                stressor: 0.0,
+                susceptibility: 0.0,
+                telematic: 0.0,
+            },
+            ..ScoringConfig::default()
+        }
+    }
+
+    fn score(vehicle: &Vehicle, telematics: Option<&TelematicsReading>) -> LeadScores {
+        calculate_lead_scores(vehicle, telematics, &ScoringConfig::default(), as_of())
+    }
+
+    #[test]
+    fn warranty_buckets_at_boundary_days() {
+        let cases = [
+            (Some(-1), 0.0, "expired"),
+            (Some(0), 1.0, "expires within 30 days"),
+            (Some(30), 1.0, "expires within 30 days"),
+            (Some(31), 0.8, "expires within 60 days"),
+            (Some(60), 0.8, "expires within 60 days"),
+            (Some(61), 0.6, "expires within 90 days"),
+            (Some(90), 0.6, "expires within 90 days"),
+            (Some(91), 0.3, "expires in more than 90 days"),
+            (None, 0.0, "no warranty date"),
+        ];
+        for (until_expiry, expected, bucket) in cases {
+            let scores = score(&vehicle(until_expiry.map(days), Some(days(-10))), None);
+            assert_eq!(scores.warranty_score, expected, "{:?} days until expiry", until_expiry);
+            assert_eq!(scores.breakdown.warranty.bucket, bucket, "{:?} days until expiry", until_expiry);
+        }
+    }
+
+    #[test]
+    fn service_buckets_at_boundary_days() {
+        let cases = [
+            (Some(0), 0.2, "within 90 days"),
+            (Some(90), 0.2, "within 90 days"),
+            (Some(91), 0.5, "more than 90 days ago"),
+            (Some(180), 0.5, "more than 90 days ago"),
+            (Some(181), 0.7, "more than 180 days ago"),
+            (Some(365), 0.7, "more than 180 days ago"),
+            (Some(366), 0.9, "more than 365 days ago"),
+            (None, 0.8, "no service record"),
+        ];
+        for (since_service, expected, bucket) in cases {
+            let scores = score(&vehicle(None, since_service.map(|n| days(-n))), None);
+            assert_eq!(scores.breakdown.service.score, expected, "{:?} days since service", since_service);
+            assert_eq!(scores.breakdown.service.bucket, bucket, "{:?} days since service", since_service);
+        }
+    }
+
+    #[test]
+    fn stressor_combines_warranty_and_service() {
+        let scores = score(&vehicle(Some(days(10)), Some(days(-400))), None);
+        assert_eq!(scores.stressor_score, 1.0_f32 * 0.6 + 0.9_f32 * 0.4);
+        assert_eq!(scores.stressor_type.as_deref(), Some("Warranty Expiring"));
+
+        let scores = score(&vehicle(None, Some(days(-400))), None);
+        assert_eq!(scores.stressor_type.as_deref(), Some("Service Overdue"));
+
+        let scores = score(&vehicle(Some(days(75)), Some(days(-200))), None);
+        assert_eq!(scores.stressor_type.as_deref(), Some("Multiple Concerns"));
+
+        let scores = score(&vehicle(Some(days(200)), Some(days(-10))), None);
+        assert_eq!(scores.stressor_type.as_deref(), Some("Maintenance Reminder"));
+    }
+
+    #[test]
+    fn susceptibility_follows_contact_status() {
+        let cases = [
+            (Some("valid"), true, true, 0.8),
+            (Some("valid"), false, false, 0.5),
+            (Some("invalid"), true, false, 0.3),
+            (Some("invalid"), false, true, 0.0),
+            (None, true, true, 0.8),
+            (None, true, false, 0.5),
+            (None, false, false, 0.3),
+        ];
+        for (status, has_email, has_zip, expected) in cases {
+            let mut v = vehicle(None, None);
+            v.contact_status = status.map(str::to_string);
+            v.customer_email = has_email.then(|| "pat@example.com".to_string());
+            v.customer_zip = has_zip.then(|| "90210".to_string());
+            let scores = score(&v, None);
+            assert_eq!(scores.susceptibility_score, expected, "{:?} email {} zip {}", status, has_email, has_zip);
+        }
+    }
+
+    #[test]
+    fn telematic_buckets_and_alerts() {
+        let v = vehicle(Some(days(200)), Some(days(-10)));
+        let cases = [
+            (None, 0.1, "no recent telematic data"),
+            (Some(reading(&["P0301"], None, None)), 1.0, "active trouble codes"),
+            (Some(reading(&[], None, Some(15.0))), 0.9, "low oil life"),
+            (Some(reading(&[], None, Some(16.0))), 0.2, "readings normal"),
+            (Some(reading(&[], Some(11.9), None)), 0.6, "low battery voltage"),
+            (Some(reading(&[], Some(12.0), Some(80.0))), 0.2, "readings normal"),
+        ];
+        for (telematics, expected, bucket) in cases {
+            let scores = score(&v, telematics.as_ref());
+            assert_eq!(scores.telematic_score, expected, "{}", bucket);
+            assert_eq!(scores.breakdown.telematic.bucket, bucket);
+            assert_eq!(scores.has_telematic, telematics.is_some());
+        }
+
+        // A trouble code or low oil brings a low-urgency lead's call forward to tomorrow
+        assert_eq!(score(&v, None).call_by_date, days(14));
+        assert_eq!(score(&v, Some(&reading(&["P0301"], None, None))).call_by_date, days(1));
+        assert_eq!(score(&v, Some(&reading(&[], None, Some(10.0)))).call_by_date, days(1));
+        assert_eq!(score(&v, Some(&reading(&[], Some(11.0), None))).call_by_date, days(14));
+    }
+
+    #[test]
+    fn weight_overrides_change_urgency() {
+        let v = vehicle(Some(days(45)), Some(days(-200)));
+
+        let scores = calculate_lead_scores(&v, None, &weighted_only(1.0, 0.0), as_of());
+        assert_eq!(scores.urgency_score, 0.8);
+
+        let scores = calculate_lead_scores(&v, None, &weighted_only(0.0, 1.0), as_of());
+        assert_eq!(scores.urgency_score, 0.7);
+
+        // The urgency score is capped at 1.0
+        let scores = calculate_lead_scores(&v, None, &weighted_only(1.0, 1.0), as_of());
+        assert_eq!(scores.urgency_score, 1.0);
+    }
+
+    // The talking points of a suggested script
+    fn script_reason(scores: &LeadScores) -> &str {
+        let script = &scores.suggested_script;
+        let start = script.find("because ").unwrap() + "because ".len();
+        &script[start..script.find(". We'd").unwrap()]
+    }
+
+    #[test]
+    fn script_talks_about_the_top_factors() {
+        let v = vehicle(Some(days(10)), Some(days(-400)));
+        let scores = score(&v, Some(&reading(&["P0301", "P0420"], None, None)));
+        assert!(scores.suggested_script.starts_with("Hi Pat Doe, this is [Your Name] from [Dealership]."));
+        assert_eq!(
+            script_reason(&scores),
+            "your factory warranty ends in 10 days, and it has been 400 days since your last service with us"
+        );
+
+        // Weighting telematics above the rest brings its trouble codes to the front
+        let mut config = ScoringConfig::default();
+        config.weights.telematic = 0.5;
+        let scores = calculate_lead_scores(&v, Some(&reading(&["P0301", "P0420"], None, None)), &config, as_of());
+        assert_eq!(
+            script_reason(&scores),
+            "your vehicle is reporting trouble codes P0301, P0420, and your factory warranty ends in 10 days"
+        );
+    }
+
+    #[test]
+    fn script_leaves_out_factors_that_did_not_raise_urgency() {
+        // Warranty far off, recent service and normal readings: nothing to raise
+        let v = vehicle(Some(days(200)), Some(days(-30)));
+        let scores = score(&v, Some(&reading(&[], Some(12.6), Some(80.0))));
+        assert_eq!(script_reason(&scores), "your vehicle is due for some routine maintenance");
+
+        // An unweighted factor contributes nothing, however high it scores
+        let v = vehicle(Some(days(0)), None);
+        let scores = calculate_lead_scores(&v, None, &weighted_only(0.0, 1.0), as_of());
+        assert_eq!(script_reason(&scores), "we don't have a service visit on file for your vehicle");
+        let scores = calculate_lead_scores(&v, Some(&reading(&[], None, Some(10.0))), &weighted_only(1.0, 0.0), as_of());
+        assert_eq!(script_reason(&scores), "your factory warranty ends today");
+    }
+
+    #[test]
+    fn breakdown_contributions_sum_to_urgency() {
+        let v = vehicle(Some(days(45)), Some(days(-200)));
+        let scores = score(&v, Some(&reading(&[], Some(11.5), None)));
+        let b = &scores.breakdown;
+        let total: f64 = [&b.warranty, &b.service, &b.stressor, &b.susceptibility, &b.telematic]
+            .iter()
+            .map(|factor| factor.contribution)
+            .sum();
+        assert!((total - scores.urgency_score as f64).abs() < 1e-4, "{} vs {}", total, scores.urgency_score);
+        assert_eq!(b.warranty.weight, 0.3);
+        assert_eq!(b.warranty.score, 0.8);
+    }
+
+    #[test]
+    fn custom_day_thresholds() {
+        let config = ScoringConfig {
+            warranty_days: [10, 20, 30],
+            service_days: [30, 60, 90],
+            ..weighted_only(1.0, 0.0)
+        };
+        let scores = calculate_lead_scores(&vehicle(Some(days(15)), Some(days(-61))), None, &config, as_of());
+        assert_eq!(scores.warranty_score, 0.8);
+        assert_eq!(scores.breakdown.service.score, 0.7);
+        assert_eq!(scores.breakdown.warranty.bucket, "expires within 20 days");
+    }
+
+    #[test]
+    fn call_windows_at_boundary_urgency() {
+        // Each window applies only above its urgency, so a score equal to it falls to the next
+        let cases = [(1, 1.0, 1), (45, 0.8, 3), (75, 0.6, 7), (200, 0.3, 14)];
+        for (until_expiry, urgency, call_days) in cases {
+            let scores =
+                calculate_lead_scores(&vehicle(Some(days(until_expiry)), None), None, &weighted_only(1.0, 0.0), as_of());
+            assert_eq!(scores.urgency_score, urgency);
+            assert_eq!(scores.call_by_date, days(call_days), "urgency {}", urgency);
+        }
+
+        let config = ScoringConfig {
+            call_windows: vec![CallWindow { above_urgency: 0.5, days: 2 }],
+            default_call_days: 30,
+            ..weighted_only(1.0, 0.0)
+        };
+        let scores = calculate_lead_scores(&vehicle(Some(days(75)), None), None, &config, as_of());
+        assert_eq!(scores.call_by_date, days(2));
+        let scores = calculate_lead_scores(&vehicle(Some(days(200)), None), None, &config, as_of());
+        assert_eq!(scores.call_by_date, days(30));
+    }
+
+    #[test]
+    fn scores_are_counted_from_as_of() {
+        let v = vehicle(Some(date(2026, 6, 1)), Some(date(2025, 9, 1)));
+        let early = calculate_lead_scores(&v, None, &ScoringConfig::default(), date(2026, 1, 1));
+        let late = calculate_lead_scores(&v, None, &ScoringConfig::default(), date(2026, 5, 15));
+        assert_eq!(early.warranty_score, 0.3);
+        assert_eq!(early.breakdown.service.score, 0.5);
+        assert_eq!(late.warranty_score, 1.0);
+        assert_eq!(late.breakdown.service.score, 0.7);
+        assert_eq!(late.inputs.as_of, date(2026, 5, 15));
+        assert_eq!(late.call_by_date, date(2026, 5, 18));
+        assert!(late.why_now.contains("Warranty expires in 17 days"), "{}", late.why_now);
+    }
+
+    #[test]
+    fn config_validation() {
+        assert!(ScoringConfig::default().validate().is_ok());
+        assert!(weighted_only(0.0, 0.0).validate().is_err());
+        assert!(weighted_only(-1.0, 1.0).validate().is_err());
+        let config = ScoringConfig { warranty_days: [30, 30, 90], ..ScoringConfig::default() };
+        assert!(config.validate().is_err());
+        let config = ScoringConfig {
+            call_windows: vec![CallWindow { above_urgency: 0.4, days: 7 }, CallWindow { above_urgency: 0.8, days: 1 }],
+            ..ScoringConfig::default()
+        };
+        assert!(config.validate().is_err());
+        let config = ScoringConfig { default_call_days: 400, ..ScoringConfig::default() };
+        assert!(config.validate().is_err());
+    }
+}
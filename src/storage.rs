@@ -0,0 +1,59 @@
+use std::env;
+use std::path::{Path, PathBuf};
+
+const DEFAULT_MAX_UPLOAD_BYTES: u64 = 50 * 1024 * 1024;
+const DEFAULT_MAX_ARCHIVE_BYTES: u64 = 500 * 1024 * 1024;
+
+/// Root directory for stored upload files (`UPLOAD_DIR`, default `uploads`).
+pub fn upload_root() -> PathBuf {
+    PathBuf::from(env::var("UPLOAD_DIR").unwrap_or_else(|_| "uploads".to_string()))
+}
+
+/// Largest upload accepted, in bytes (`MAX_UPLOAD_BYTES`, default 50 MB).
+pub fn max_upload_bytes() -> u64 {
+    env::var("MAX_UPLOAD_BYTES")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_MAX_UPLOAD_BYTES)
+}
+
+/// Most bytes all of an archive's files may decompress to together
+/// (`MAX_ARCHIVE_BYTES`, default 500 MB).
+pub fn max_archive_bytes() -> u64 {
+    env::var("MAX_ARCHIVE_BYTES")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_MAX_ARCHIVE_BYTES)
+}
+
+/// Directory holding one dealer's uploads, so dealers never share file names.
+pub fn dealer_upload_dir(dealer_id: i32) -> PathBuf {
+    upload_root().join(dealer_id.to_string())
+}
+
+/// A server-generated file name that keeps only the (sanitized) extension of the
+/// client's file name, which the readers use to pick CSV or Excel parsing.
+pub fn generated_file_name(original: &str) -> String {
+    let extension: String = Path::new(original)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .take(10)
+        .collect::<String>()
+        .to_ascii_lowercase();
+
+    if extension.is_empty() {
+        uuid::Uuid::new_v4().to_string()
+    } else {
+        format!("{}.{}", uuid::Uuid::new_v4(), extension)
+    }
+}
+
+/// The client's file name without any directory components, for display only.
+pub fn display_file_name(original: &str) -> String {
+    let name = original.rsplit(['/', '\\']).next().unwrap_or(original).trim();
+    let name = if name.is_empty() { "upload" } else { name };
+    name.chars().take(255).collect()
+}
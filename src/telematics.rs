@@ -0,0 +1,427 @@
+use crate::ingest::normalize_header;
+use crate::models::TelematicsReading;
+use crate::vin::decode_vin;
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, Utc};
+use csv::ReaderBuilder;
+use sqlx::PgConnection;
+use std::collections::HashMap;
+
+/// Readings older than this many days before the scoring date are ignored.
+pub const RECENT_READING_DAYS: i64 = 30;
+
+// Readings may be stamped slightly ahead of the server's clock, but not by days
+const MAX_CLOCK_SKEW_HOURS: i64 = 24;
+
+/// A reading as submitted, before validation. JSON values and CSV cells are both
+/// read as text; several trouble codes are separated by `;`, `,` or spaces.
+#[derive(Debug, Default)]
+pub struct RawReading {
+    pub vin: Option<String>,
+    pub recorded_at: Option<String>,
+    pub odometer_miles: Option<String>,
+    pub dtc_codes: Option<String>,
+    pub battery_voltage: Option<String>,
+    pub oil_life_percent: Option<String>,
+}
+
+/// Parses a `POST /api/telematics` body: a JSON array of reading objects, or CSV
+/// with a header row. Records that can't be read are kept as errors so each result
+/// lines up with its position in the request.
+pub fn parse_readings(body: &[u8]) -> Result<Vec<Result<RawReading, String>>, String> {
+    let text = std::str::from_utf8(body).map_err(|_| "Request body must be UTF-8".to_string())?;
+    let text = text.trim_start_matches('\u{feff}');
+    if text.trim_start().starts_with('[') {
+        let values: Vec<serde_json::Value> =
+            serde_json::from_str(text).map_err(|e| format!("Invalid JSON array: {}", e))?;
+        return Ok(values.iter().map(raw_from_json).collect());
+    }
+    parse_csv(text)
+}
+
+fn raw_from_json(value: &serde_json::Value) -> Result<RawReading, String> {
+    let object = value
+        .as_object()
+        .ok_or_else(|| "Invalid record: expected a JSON object".to_string())?;
+    let field = |name: &str| match object.get(name) {
+        None | Some(serde_json::Value::Null) => None,
+        Some(serde_json::Value::String(s)) => Some(s.clone()),
+        Some(serde_json::Value::Array(items)) => Some(
+            items
+                .iter()
+                .map(|item| item.as_str().map(str::to_string).unwrap_or_else(|| item.to_string()))
+                .collect::<Vec<_>>()
+                .join(";"),
+        ),
+        Some(other) => Some(other.to_string()),
+    };
+    Ok(RawReading {
+        vin: field("vin"),
+        recorded_at: field("recorded_at"),
+        odometer_miles: field("odometer_miles"),
+        dtc_codes: field("dtc_codes"),
+        battery_voltage: field("battery_voltage"),
+        oil_life_percent: field("oil_life_percent"),
+    })
+}
+
+// Header names accepted for each field, normalized as in `ingest`
+const CSV_COLUMNS: [(&str, &[&str]); 6] = [
+    ("vin", &["vin"]),
+    ("recorded_at", &["recordedat", "timestamp", "readingtime"]),
+    ("odometer_miles", &["odometermiles", "odometer", "mileage"]),
+    ("dtc_codes", &["dtccodes", "dtcs", "dtc", "troublecodes"]),
+    ("battery_voltage", &["batteryvoltage", "battery", "batteryvolts"]),
+    ("oil_life_percent", &["oillifepercent", "oillife"]),
+];
+
+fn parse_csv(text: &str) -> Result<Vec<Result<RawReading, String>>, String> {
+    let mut reader = ReaderBuilder::new().flexible(true).from_reader(text.as_bytes());
+    let headers: Vec<String> = reader
+        .headers()
+        .map_err(|e| format!("Invalid CSV: {}", e))?
+        .iter()
+        .map(normalize_header)
+        .collect();
+    let index = |column: &str| {
+        let (_, aliases) = CSV_COLUMNS.iter().find(|(name, _)| *name == column)?;
+        headers.iter().position(|h| aliases.contains(&h.as_str()))
+    };
+    let (Some(vin), Some(recorded_at)) = (index("vin"), index("recorded_at")) else {
+        return Err("CSV readings need a vin and a recorded_at column".to_string());
+    };
+    let columns = [
+        Some(vin),
+        Some(recorded_at),
+        index("odometer_miles"),
+        index("dtc_codes"),
+        index("battery_voltage"),
+        index("oil_life_percent"),
+    ];
+
+    Ok(reader
+        .records()
+        .map(|record| {
+            let record = record.map_err(|e| format!("Invalid CSV row: {}", e))?;
+            let [vin, recorded_at, odometer_miles, dtc_codes, battery_voltage, oil_life_percent] = columns.map(|i| {
+                i.and_then(|i| record.get(i))
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+                    .map(str::to_string)
+            });
+            Ok(RawReading {
+                vin,
+                recorded_at,
+                odometer_miles,
+                dtc_codes,
+                battery_voltage,
+                oil_life_percent,
+            })
+        })
+        .collect())
+}
+
+/// Validates a reading, returning every problem found with it.
+pub fn validate_reading(raw: &RawReading) -> Result<TelematicsReading, Vec<String>> {
+    let mut errors = Vec::new();
+
+    let vin = match raw.vin.as_deref().map(decode_vin) {
+        Some(Ok(decoded)) => Some(decoded.vin),
+        Some(Err(reason)) => {
+            errors.push(format!("vin: {}", reason));
+            None
+        }
+        None => {
+            errors.push("vin: Missing".to_string());
+            None
+        }
+    };
+
+    let recorded_at = match raw.recorded_at.as_deref().map(str::trim) {
+        Some(value) => match parse_timestamp(value) {
+            Some(at) if at > Utc::now() + Duration::hours(MAX_CLOCK_SKEW_HOURS) => {
+                errors.push(format!("recorded_at: {} is in the future", value));
+                None
+            }
+            Some(at) => Some(at),
+            None => {
+                errors.push(format!(
+                    "recorded_at: Unrecognized timestamp '{}' (use RFC 3339, e.g. 2025-03-04T08:15:00Z)",
+                    value
+                ));
+                None
+            }
+        },
+        None => {
+            errors.push("recorded_at: Missing".to_string());
+            None
+        }
+    };
+
+    let odometer_miles = parse_number::<i32>(&raw.odometer_miles, "odometer_miles", 0.0, 2_000_000.0, &mut errors);
+    let battery_voltage = parse_number::<f32>(&raw.battery_voltage, "battery_voltage", 0.0, 30.0, &mut errors);
+    let oil_life_percent = parse_number::<f32>(&raw.oil_life_percent, "oil_life_percent", 0.0, 100.0, &mut errors);
+
+    let mut dtc_codes = Vec::new();
+    for code in raw
+        .dtc_codes
+        .as_deref()
+        .unwrap_or_default()
+        .split(|c: char| c == ';' || c == ',' || c.is_whitespace())
+        .filter(|code| !code.is_empty())
+    {
+        let code = code.to_ascii_uppercase();
+        if is_dtc(&code) {
+            if !dtc_codes.contains(&code) {
+                dtc_codes.push(code);
+            }
+        } else {
+            errors.push(format!("dtc_codes: '{}' is not a diagnostic trouble code such as P0301", code));
+        }
+    }
+
+    match (vin, recorded_at) {
+        (Some(vin), Some(recorded_at)) if errors.is_empty() => Ok(TelematicsReading {
+            vin,
+            recorded_at,
+            odometer_miles,
+            dtc_codes,
+            battery_voltage,
+            oil_life_percent,
+        }),
+        _ => Err(errors),
+    }
+}
+
+// RFC 3339, or a date and time without an offset (taken as UTC), or a bare date
+fn parse_timestamp(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(at) = DateTime::parse_from_rfc3339(value) {
+        return Some(at.with_timezone(&Utc));
+    }
+    for format in ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"] {
+        if let Ok(at) = NaiveDateTime::parse_from_str(value, format) {
+            return Some(at.and_utc());
+        }
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .map(|date| date.and_hms_opt(0, 0, 0).unwrap().and_utc())
+}
+
+fn parse_number<T: std::str::FromStr + Into<f64> + Copy>(
+    value: &Option<String>,
+    name: &str,
+    min: f64,
+    max: f64,
+    errors: &mut Vec<String>,
+) -> Option<T> {
+    let value = value.as_deref()?.trim();
+    match value.parse::<T>() {
+        Ok(number) if (min..=max).contains(&number.into()) => Some(number),
+        _ => {
+            errors.push(format!("{}: '{}' must be a number from {} to {}", name, value, min, max));
+            None
+        }
+    }
+}
+
+// An OBD-II code: P (powertrain), B (body), C (chassis) or U (network), then four hex digits
+fn is_dtc(code: &str) -> bool {
+    let bytes = code.as_bytes();
+    bytes.len() == 5
+        && matches!(bytes[0], b'P' | b'B' | b'C' | b'U')
+        && matches!(bytes[1], b'0'..=b'3')
+        && bytes[2..].iter().all(u8::is_ascii_hexdigit)
+}
+
+/// Stores readings, replacing any the dealer already has for the same VIN and time.
+pub async fn store_readings(conn: &mut PgConnection, dealer_id: i32, readings: &[TelematicsReading]) -> Result<(), sqlx::Error> {
+    // Postgres can't UNNEST a TEXT[][] into rows of arrays, so codes travel as joined text
+    sqlx::query(
+        "INSERT INTO telematics_readings (dealer_id, vin, recorded_at, odometer_miles, dtc_codes, battery_voltage, oil_life_percent) \
+         SELECT $1, vin, recorded_at, odometer_miles, string_to_array(dtc_codes, ';'), battery_voltage, oil_life_percent \
+         FROM UNNEST($2::VARCHAR[], $3::TIMESTAMPTZ[], $4::INTEGER[], $5::TEXT[], $6::REAL[], $7::REAL[]) \
+         AS t(vin, recorded_at, odometer_miles, dtc_codes, battery_voltage, oil_life_percent) \
+         ON CONFLICT (dealer_id, vin, recorded_at) DO UPDATE SET odometer_miles = EXCLUDED.odometer_miles, dtc_codes = EXCLUDED.dtc_codes, \
+         battery_voltage = EXCLUDED.battery_voltage, oil_life_percent = EXCLUDED.oil_life_percent, created_at = NOW()"
+    )
+    .bind(dealer_id)
+    .bind(readings.iter().map(|r| r.vin.clone()).collect::<Vec<_>>())
+    .bind(readings.iter().map(|r| r.recorded_at).collect::<Vec<_>>())
+    .bind(readings.iter().map(|r| r.odometer_miles).collect::<Vec<_>>())
+    .bind(readings.iter().map(|r| r.dtc_codes.join(";")).collect::<Vec<_>>())
+    .bind(readings.iter().map(|r| r.battery_voltage).collect::<Vec<_>>())
+    .bind(readings.iter().map(|r| r.oil_life_percent).collect::<Vec<_>>())
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Each VIN's latest reading from the `RECENT_READING_DAYS` up to the end of
+/// `as_of`, for the given VINs or, when `vins` is `None`, all of the dealer's.
+pub async fn recent_readings(
+    conn: &mut PgConnection,
+    dealer_id: i32,
+    vins: Option<&[String]>,
+    as_of: NaiveDate,
+) -> Result<HashMap<String, TelematicsReading>, sqlx::Error> {
+    let end = (as_of + Duration::days(1)).and_hms_opt(0, 0, 0).unwrap().and_utc();
+    let readings = sqlx::query_as::<_, TelematicsReading>(
+        "SELECT DISTINCT ON (vin) vin, recorded_at, odometer_miles, dtc_codes, battery_voltage, oil_life_percent \
+         FROM telematics_readings WHERE dealer_id = $1 AND ($2::VARCHAR[] IS NULL OR vin = ANY($2)) \
+         AND recorded_at < $3 AND recorded_at >= $4 ORDER BY vin, recorded_at DESC"
+    )
+    .bind(dealer_id)
+    .bind(vins)
+    .bind(end)
+    .bind(end - Duration::days(RECENT_READING_DAYS + 1))
+    .fetch_all(conn)
+    .await?;
+    Ok(readings.into_iter().map(|r| (r.vin.clone(), r)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reading(recorded_at: &str) -> RawReading {
+        RawReading {
+            vin: Some("1HGCM82633A004352".to_string()),
+            recorded_at: Some(recorded_at.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn utc(value: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(value).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn timestamp_formats_are_read() {
+        let cases = [
+            ("2025-03-04T08:15:00Z", Some("2025-03-04T08:15:00Z")),
+            ("2025-03-04T08:15:00-05:00", Some("2025-03-04T13:15:00Z")),
+            ("2025-03-04T08:15:00.250+01:00", Some("2025-03-04T07:15:00.250Z")),
+            ("2025-03-04T08:15:00", Some("2025-03-04T08:15:00Z")),
+            ("2025-03-04 08:15:00", Some("2025-03-04T08:15:00Z")),
+            ("2025-03-04 08:15", Some("2025-03-04T08:15:00Z")),
+            ("2025-03-04", Some("2025-03-04T00:00:00Z")),
+            ("03/04/2025", None),
+            ("2025-02-30", None),
+            ("1741076100", None),
+            ("", None),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(parse_timestamp(value), expected.map(utc), "{}", value);
+        }
+    }
+
+    #[test]
+    fn unrecognized_timestamp_is_rejected() {
+        let errors = validate_reading(&reading("yesterday")).unwrap_err();
+        assert_eq!(
+            errors,
+            ["recorded_at: Unrecognized timestamp 'yesterday' (use RFC 3339, e.g. 2025-03-04T08:15:00Z)"]
+        );
+        let errors = validate_reading(&RawReading { recorded_at: None, ..reading("") }).unwrap_err();
+        assert_eq!(errors, ["recorded_at: Missing"]);
+    }
+
+    #[test]
+    fn future_readings_beyond_clock_skew_are_rejected() {
+        let within = (Utc::now() + Duration::hours(MAX_CLOCK_SKEW_HOURS - 1)).to_rfc3339();
+        assert!(validate_reading(&reading(&within)).is_ok());
+
+        let beyond = (Utc::now() + Duration::hours(MAX_CLOCK_SKEW_HOURS + 1)).to_rfc3339();
+        let errors = validate_reading(&reading(&beyond)).unwrap_err();
+        assert_eq!(errors, [format!("recorded_at: {} is in the future", beyond)]);
+    }
+
+    #[test]
+    fn trouble_codes_are_uppercased_and_deduplicated() {
+        let cases = [
+            ("P0301", vec!["P0301"]),
+            ("p0301;P0301, p0301", vec!["P0301"]),
+            ("u0100 c0035;b1a2f", vec!["U0100", "C0035", "B1A2F"]),
+            (" ;, ", vec![]),
+        ];
+        for (codes, expected) in cases {
+            let raw = RawReading {
+                dtc_codes: Some(codes.to_string()),
+                ..reading("2025-03-04T08:15:00Z")
+            };
+            assert_eq!(validate_reading(&raw).unwrap().dtc_codes, expected, "{}", codes);
+        }
+    }
+
+    #[test]
+    fn invalid_trouble_codes_are_rejected() {
+        let raw = RawReading {
+            dtc_codes: Some("P0301;x0301;p4301;P03".to_string()),
+            ..reading("2025-03-04T08:15:00Z")
+        };
+        let errors = validate_reading(&raw).unwrap_err();
+        assert_eq!(
+            errors,
+            [
+                "dtc_codes: 'X0301' is not a diagnostic trouble code such as P0301",
+                "dtc_codes: 'P4301' is not a diagnostic trouble code such as P0301",
+                "dtc_codes: 'P03' is not a diagnostic trouble code such as P0301",
+            ]
+        );
+    }
+
+    #[test]
+    fn out_of_range_numbers_are_rejected() {
+        let raw = RawReading {
+            odometer_miles: Some("-5".to_string()),
+            battery_voltage: Some("12.4".to_string()),
+            oil_life_percent: Some("101".to_string()),
+            ..reading("2025-03-04T08:15:00Z")
+        };
+        let errors = validate_reading(&raw).unwrap_err();
+        assert_eq!(
+            errors,
+            [
+                "odometer_miles: '-5' must be a number from 0 to 2000000",
+                "oil_life_percent: '101' must be a number from 0 to 100",
+            ]
+        );
+    }
+
+    #[test]
+    fn csv_header_aliases_are_recognized() {
+        let body = "\u{feff}VIN,Timestamp,Mileage,Trouble Codes,Battery Volts,Oil Life\n\
+                    1HGCM82633A004352,2025-03-04 08:15,41000,P0301;P0420,12.1,35\n\
+                    1HGCM82633A004352,2025-03-05,,,,\n";
+        let records = parse_readings(body.as_bytes()).unwrap();
+        assert_eq!(records.len(), 2);
+
+        let first = records[0].as_ref().unwrap();
+        assert_eq!(first.vin.as_deref(), Some("1HGCM82633A004352"));
+        assert_eq!(first.recorded_at.as_deref(), Some("2025-03-04 08:15"));
+        assert_eq!(first.odometer_miles.as_deref(), Some("41000"));
+        assert_eq!(first.dtc_codes.as_deref(), Some("P0301;P0420"));
+        assert_eq!(first.battery_voltage.as_deref(), Some("12.1"));
+        assert_eq!(first.oil_life_percent.as_deref(), Some("35"));
+
+        let second = records[1].as_ref().unwrap();
+        assert_eq!(second.recorded_at.as_deref(), Some("2025-03-05"));
+        assert!(second.odometer_miles.is_none() && second.dtc_codes.is_none());
+    }
+
+    #[test]
+    fn csv_without_vin_or_timestamp_is_refused() {
+        let error = parse_readings(b"vin,odometer\n1HGCM82633A004352,41000\n").unwrap_err();
+        assert_eq!(error, "CSV readings need a vin and a recorded_at column");
+    }
+
+    #[test]
+    fn json_arrays_are_read() {
+        let body = r#"[{"vin": "1HGCM82633A004352", "recorded_at": "2025-03-04", "odometer_miles": 41000, "dtc_codes": ["P0301", "p0420"]}, 7]"#;
+        let records = parse_readings(body.as_bytes()).unwrap();
+        let first = records[0].as_ref().unwrap();
+        assert_eq!(first.odometer_miles.as_deref(), Some("41000"));
+        assert_eq!(first.dtc_codes.as_deref(), Some("P0301;p0420"));
+        assert_eq!(records[1].as_ref().unwrap_err(), "Invalid record: expected a JSON object");
+    }
+}
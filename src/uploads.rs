@@ -0,0 +1,362 @@
+use crate::archives::{extract_archive, ArchiveFormat, ExtractedFile};
+use crate::jobs::{enqueue_upload, JobMode};
+use crate::models::Dealer;
+use crate::quotas::{check_new_upload, DealerLimits};
+use crate::reader::count_rows;
+use crate::storage::dealer_upload_dir;
+use sqlx::{PgConnection, PgPool};
+use std::path::PathBuf;
+
+/// A file stored in the dealer's upload directory, ready to become an upload.
+#[derive(Debug, Clone)]
+pub struct NewUpload {
+    pub dealer_id: i32,
+    /// The client's file name, for display
+    pub filename: String,
+    pub file_path: PathBuf,
+    pub all_or_nothing: bool,
+    pub sheet_name: Option<String>,
+    /// Adapter id, or `None` to detect it from the header row
+    pub adapter: Option<String>,
+    /// `file` for the upload form, `watch` for a watched directory, `api` for a vehicle batch
+    pub source: &'static str,
+    /// For a `watch` upload, the claimed file in the watched directory it was made from
+    pub watch_path: Option<String>,
+}
+
+/// Why `create_upload` didn't create an upload.
+#[derive(Debug)]
+pub enum CreateUploadError {
+    /// The file can't be accepted; the message is meant for the dealer
+    Rejected(String),
+    Failed(anyhow::Error),
+}
+
+/// Creates an upload for a stored file and queues it for processing. An archive's
+/// upload is recorded as `extracting` while its files are extracted, then each file
+/// is queued as a child upload. The upload is refused when it would take the dealer
+/// over one of its limits. If nothing is created, the stored file and anything
+/// extracted from it are removed.
+pub async fn create_upload(pool: &PgPool, upload: NewUpload) -> Result<i32, CreateUploadError> {
+    let archive = match ArchiveFormat::from_path(&upload.filename) {
+        Some(format) => match insert_archive_upload(pool, &upload).await {
+            Ok(archive_id) => Some((format, archive_id)),
+            Err(e) => {
+                let _ = tokio::fs::remove_file(&upload.file_path).await;
+                return Err(CreateUploadError::Failed(e.into()));
+            }
+        },
+        None => None,
+    };
+    let archive_id = archive.map(|(_, archive_id)| archive_id);
+
+    let extracted = match archive {
+        Some((format, archive_id)) => {
+            let (archive_path, archive_name, dest_dir) = (
+                upload.file_path.clone(),
+                upload.filename.clone(),
+                dealer_upload_dir(upload.dealer_id),
+            );
+            let result = tokio::task::spawn_blocking(move || extract_archive(&archive_path, &archive_name, format, &dest_dir))
+                .await
+                .unwrap_or_else(|e| Err(e.into()));
+            match result {
+                Ok(files) => files,
+                Err(e) => {
+                    discard_archive_upload(pool, archive_id).await;
+                    let _ = tokio::fs::remove_file(&upload.file_path).await;
+                    return Err(CreateUploadError::Rejected(format!("Could not read the archive: {}", e)));
+                }
+            }
+        }
+        None => Vec::new(),
+    };
+
+    let result = match check_quotas(pool, &upload, &extracted).await {
+        Ok(None) => insert_uploads(pool, &upload, archive_id, &extracted).await.map_err(CreateUploadError::Failed),
+        Ok(Some(reason)) => Err(CreateUploadError::Rejected(reason)),
+        Err(e) => Err(CreateUploadError::Failed(e)),
+    };
+    if result.is_err() {
+        if let Some(archive_id) = archive_id {
+            discard_archive_upload(pool, archive_id).await;
+        }
+        let _ = tokio::fs::remove_file(&upload.file_path).await;
+        for file in &extracted {
+            let _ = tokio::fs::remove_file(&file.path).await;
+        }
+    }
+    result
+}
+
+// Rows are counted only up to the limit; files that can't be read are left for the
+// import to report.
+async fn check_quotas(pool: &PgPool, upload: &NewUpload, extracted: &[ExtractedFile]) -> anyhow::Result<Option<String>> {
+    let limits = DealerLimits::for_dealer(pool, upload.dealer_id).await?;
+    let files: Vec<(String, String)> = if extracted.is_empty() {
+        vec![(upload.filename.clone(), upload.file_path.to_string_lossy().to_string())]
+    } else {
+        extracted
+            .iter()
+            .map(|file| (file.filename.clone(), file.path.to_string_lossy().to_string()))
+            .collect()
+    };
+    let sheet_name = upload.sheet_name.clone();
+    let limit = limits.rows_per_upload as usize;
+    let file_rows = tokio::task::spawn_blocking(move || {
+        files
+            .into_iter()
+            .filter_map(|(filename, path)| {
+                count_rows(&path, sheet_name.as_deref(), limit)
+                    .ok()
+                    .map(|rows| (filename, rows))
+            })
+            .collect::<Vec<_>>()
+    })
+    .await?;
+
+    Ok(check_new_upload(pool, upload.dealer_id, &limits, &file_rows).await?)
+}
+
+// Records an archive's upload, with no files yet, before it is extracted.
+async fn insert_archive_upload(pool: &PgPool, upload: &NewUpload) -> Result<i32, sqlx::Error> {
+    let mut conn = pool.acquire().await?;
+    insert_upload_row(&mut conn, upload, "extracting").await
+}
+
+// Removes an archive's upload that never got its files.
+async fn discard_archive_upload(pool: &PgPool, archive_id: i32) {
+    let _ = sqlx::query("DELETE FROM uploads WHERE id = $1 AND status = 'extracting'")
+        .bind(archive_id)
+        .execute(pool)
+        .await;
+}
+
+async fn insert_upload_row(conn: &mut PgConnection, upload: &NewUpload, status: &str) -> Result<i32, sqlx::Error> {
+    sqlx::query_scalar(
+        "INSERT INTO uploads (dealer_id, filename, file_path, status, all_or_nothing, sheet_name, adapter, source, watch_path) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) RETURNING id"
+    )
+    .bind(upload.dealer_id)
+    .bind(&upload.filename)
+    .bind(upload.file_path.to_string_lossy().to_string())
+    .bind(status)
+    .bind(upload.all_or_nothing)
+    .bind(&upload.sheet_name)
+    .bind(upload.adapter.as_ref().map(|id| id.to_ascii_lowercase()))
+    .bind(upload.source)
+    .bind(&upload.watch_path)
+    .fetch_one(conn)
+    .await
+}
+
+// Inserts and queues the upload, or for an archive's upload (`archive_id`) a child
+// upload per extracted file, which moves the archive on to `processing`.
+async fn insert_uploads(
+    pool: &PgPool,
+    upload: &NewUpload,
+    archive_id: Option<i32>,
+    extracted: &[ExtractedFile],
+) -> anyhow::Result<i32> {
+    let adapter = upload.adapter.as_ref().map(|id| id.to_ascii_lowercase());
+    let mut tx = pool.begin().await?;
+    let upload_id = match archive_id {
+        Some(archive_id) => {
+            let updated = sqlx::query("UPDATE uploads SET status = 'processing', child_count = $1 WHERE id = $2 AND status = 'extracting'")
+                .bind(extracted.len() as i32)
+                .bind(archive_id)
+                .execute(&mut *tx)
+                .await?;
+            if updated.rows_affected() == 0 {
+                return Err(anyhow::anyhow!("Upload {} was removed while its archive was extracted", archive_id));
+            }
+            archive_id
+        }
+        None => {
+            let upload_id = insert_upload_row(&mut tx, upload, "processing").await?;
+            enqueue_upload(&mut tx, upload_id, JobMode::Import).await?;
+            upload_id
+        }
+    };
+    for file in extracted {
+        let child_id: i32 = sqlx::query_scalar(
+            "INSERT INTO uploads (dealer_id, filename, file_path, status, all_or_nothing, sheet_name, adapter, source, parent_id) VALUES ($1, $2, $3, 'processing', $4, $5, $6, $7, $8) RETURNING id"
+        )
+        .bind(upload.dealer_id)
+        .bind(&file.filename)
+        .bind(file.path.to_string_lossy().to_string())
+        .bind(upload.all_or_nothing)
+        .bind(&upload.sheet_name)
+        .bind(&adapter)
+        .bind(upload.source)
+        .bind(upload_id)
+        .fetch_one(&mut *tx)
+        .await?;
+        enqueue_upload(&mut tx, child_id, JobMode::Import).await?;
+    }
+    tx.commit().await?;
+    Ok(upload_id)
+}
+
+/// What removing an upload, or the vehicles a reparse no longer contains, did to the
+/// dealer's vehicles.
+#[derive(Debug, Clone, Copy)]
+pub struct UploadRemoval {
+    pub vehicles_deleted: i64,
+    pub vehicles_kept: i64,
+}
+
+/// Removes an upload and the vehicles only it contains, inside the caller's transaction.
+///
+/// Vehicles are shared between every upload that included their VIN, so a vehicle
+/// that another upload also contains is kept, along with its current values, and its
+/// vehicle and lead are moved to the latest of those uploads. Deleting the upload
+/// row cascades to its row errors and job.
+pub async fn remove_upload(conn: &mut PgConnection, upload_id: i32, dealer_id: i32) -> anyhow::Result<UploadRemoval> {
+    let removal = unlink_vehicles(conn, upload_id, dealer_id, &[]).await?;
+
+    sqlx::query("DELETE FROM uploads WHERE id = $1")
+        .bind(upload_id)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(removal)
+}
+
+/// Unlinks every vehicle of an upload whose VIN isn't in `keep_vins`, inside the
+/// caller's transaction, as `remove_upload` does for all of them: vehicles no other
+/// upload contains are deleted with their leads, and the rest move to the latest
+/// upload that contains them.
+pub async fn unlink_vehicles(
+    conn: &mut PgConnection,
+    upload_id: i32,
+    dealer_id: i32,
+    keep_vins: &[String],
+) -> anyhow::Result<UploadRemoval> {
+    // Hold off the dealer's other uploads while vehicles change hands
+    sqlx::query("SELECT pg_advisory_xact_lock($1)")
+        .bind(dealer_id as i64)
+        .execute(&mut *conn)
+        .await?;
+
+    let linked: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM vehicle_uploads l JOIN vehicles v ON v.id = l.vehicle_id \
+         WHERE l.upload_id = $1 AND NOT (v.vin = ANY($2))"
+    )
+    .bind(upload_id)
+    .bind(keep_vins)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let vehicles_deleted = sqlx::query(
+        "DELETE FROM vehicles v WHERE v.dealer_id = $2 AND NOT (v.vin = ANY($3)) \
+         AND (v.upload_id = $1 OR EXISTS (SELECT 1 FROM vehicle_uploads l WHERE l.vehicle_id = v.id AND l.upload_id = $1)) \
+         AND NOT EXISTS (SELECT 1 FROM vehicle_uploads o WHERE o.vehicle_id = v.id AND o.upload_id <> $1)"
+    )
+    .bind(upload_id)
+    .bind(dealer_id)
+    .bind(keep_vins)
+    .execute(&mut *conn)
+    .await?
+    .rows_affected() as i64;
+
+    sqlx::query(
+        "DELETE FROM vehicle_uploads l USING vehicles v \
+         WHERE l.vehicle_id = v.id AND l.upload_id = $1 AND NOT (v.vin = ANY($2))"
+    )
+    .bind(upload_id)
+    .bind(keep_vins)
+    .execute(&mut *conn)
+    .await?;
+
+    // `upload_id` cascades on delete, so anything still pointing here has to move first
+    sqlx::query(
+        "UPDATE vehicles v SET upload_id = ( \
+             SELECT MAX(o.upload_id) FROM vehicle_uploads o WHERE o.vehicle_id = v.id AND o.upload_id <> $1 \
+         ) WHERE v.upload_id = $1 AND NOT (v.vin = ANY($2))"
+    )
+    .bind(upload_id)
+    .bind(keep_vins)
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query(
+        "UPDATE scored_leads sl SET upload_id = v.upload_id FROM vehicles v \
+         WHERE sl.vehicle_id = v.id AND sl.upload_id = $1 AND NOT (v.vin = ANY($2))"
+    )
+    .bind(upload_id)
+    .bind(keep_vins)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(UploadRemoval {
+        vehicles_deleted,
+        vehicles_kept: (linked - vehicles_deleted).max(0),
+    })
+}
+
+/// Updates the archive upload that `upload_id` was extracted from, if any, with
+/// `refresh_archive_upload`.
+pub async fn refresh_parent_upload(conn: &mut PgConnection, upload_id: i32) -> Result<(), sqlx::Error> {
+    let parent_id: Option<i32> = sqlx::query_scalar("SELECT parent_id FROM uploads WHERE id = $1")
+        .bind(upload_id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .flatten();
+    match parent_id {
+        Some(parent_id) => refresh_archive_upload(conn, parent_id).await,
+        None => Ok(()),
+    }
+}
+
+/// Rolls the status and counts of an archive's child uploads up into the archive's upload.
+///
+/// The archive is `processing` while any child is, then `completed` when every child
+/// completed, `error` when every child failed, and `partial` otherwise.
+pub async fn refresh_archive_upload(conn: &mut PgConnection, parent_id: i32) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE uploads p SET \
+             child_count = c.total, row_count = c.row_count, processed_count = c.processed_count, \
+             new_count = c.new_count, updated_count = c.updated_count, unchanged_count = c.unchanged_count, \
+             rows_failed = c.rows_failed, \
+             status = CASE WHEN c.pending > 0 THEN 'processing' WHEN c.failed = 0 THEN 'completed' \
+                           WHEN c.failed = c.total THEN 'error' ELSE 'partial' END, \
+             phase = CASE WHEN c.pending > 0 THEN 'importing' WHEN c.failed > 0 AND c.failed = c.total THEN 'error' ELSE 'completed' END, \
+             error_message = CASE WHEN c.failed > 0 THEN c.failed || ' of ' || c.total || ' file(s) failed' END \
+         FROM ( \
+             SELECT COUNT(*)::INT AS total, \
+                    COUNT(*) FILTER (WHERE status = 'processing')::INT AS pending, \
+                    COUNT(*) FILTER (WHERE status = 'error')::INT AS failed, \
+                    COALESCE(SUM(row_count), 0)::INT AS row_count, COALESCE(SUM(processed_count), 0)::INT AS processed_count, \
+                    COALESCE(SUM(new_count), 0)::INT AS new_count, COALESCE(SUM(updated_count), 0)::INT AS updated_count, \
+                    COALESCE(SUM(unchanged_count), 0)::INT AS unchanged_count, COALESCE(SUM(rows_failed), 0)::INT AS rows_failed \
+             FROM uploads WHERE parent_id = $1 \
+         ) c \
+         WHERE p.id = $1"
+    )
+    .bind(parent_id)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Appends an entry to `audit_log` for an action taken by `dealer`.
+pub async fn record_audit(
+    conn: &mut PgConnection,
+    dealer: &Dealer,
+    action: &str,
+    entity_type: &str,
+    entity_id: i32,
+    details: serde_json::Value,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO audit_log (dealer_id, actor_email, action, entity_type, entity_id, details) VALUES ($1, $2, $3, $4, $5, $6)"
+    )
+    .bind(dealer.id)
+    .bind(&dealer.email)
+    .bind(action)
+    .bind(entity_type)
+    .bind(entity_id)
+    .bind(details)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
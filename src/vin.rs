@@ -0,0 +1,288 @@
+// VIN validation (ISO 3779 / 49 CFR 565) and offline decoding of the
+// manufacturer, model year and plant code.
+
+const WEIGHTS: [u32; 17] = [8, 7, 6, 5, 4, 3, 2, 10, 0, 9, 8, 7, 6, 5, 4, 3, 2];
+
+// Model year codes in position 10 repeat on a 30-year cycle starting at 1980.
+const YEAR_CODES: &[u8; 30] = b"ABCDEFGHJKLMNPRSTVWXY123456789";
+
+// World Manufacturer Identifiers for the makes our dealers see most often.
+// Entries are matched on the full three-character WMI first, then on the
+// first two characters, so two-character entries act as catch-alls.
+const WMI_TABLE: &[(&str, &str)] = &[
+    ("1C3", "Chrysler"),
+    ("1C4", "Chrysler"),
+    ("1C6", "Ram"),
+    ("1FA", "Ford"),
+    ("1FD", "Ford"),
+    ("1FM", "Ford"),
+    ("1FT", "Ford"),
+    ("1G1", "Chevrolet"),
+    ("1G4", "Buick"),
+    ("1G6", "Cadillac"),
+    ("1GC", "Chevrolet"),
+    ("1GK", "GMC"),
+    ("1GN", "Chevrolet"),
+    ("1GT", "GMC"),
+    ("1GY", "Cadillac"),
+    ("1HG", "Honda"),
+    ("1J4", "Jeep"),
+    ("1J8", "Jeep"),
+    ("1LN", "Lincoln"),
+    ("1N4", "Nissan"),
+    ("1N6", "Nissan"),
+    ("1VW", "Volkswagen"),
+    ("1YV", "Mazda"),
+    ("19U", "Acura"),
+    ("19X", "Honda"),
+    ("2C3", "Chrysler"),
+    ("2C4", "Chrysler"),
+    ("2FM", "Ford"),
+    ("2G1", "Chevrolet"),
+    ("2GT", "GMC"),
+    ("2HG", "Honda"),
+    ("2HK", "Honda"),
+    ("2HN", "Acura"),
+    ("2T1", "Toyota"),
+    ("2T2", "Lexus"),
+    ("2T3", "Toyota"),
+    ("3C4", "Chrysler"),
+    ("3C6", "Ram"),
+    ("3FA", "Ford"),
+    ("3G1", "Chevrolet"),
+    ("3GC", "Chevrolet"),
+    ("3GN", "Chevrolet"),
+    ("3GT", "GMC"),
+    ("3HG", "Honda"),
+    ("3KP", "Kia"),
+    ("3N1", "Nissan"),
+    ("3N6", "Nissan"),
+    ("3VW", "Volkswagen"),
+    ("4JG", "Mercedes-Benz"),
+    ("4S3", "Subaru"),
+    ("4S4", "Subaru"),
+    ("4T1", "Toyota"),
+    ("4T3", "Toyota"),
+    ("4T4", "Toyota"),
+    ("5FN", "Honda"),
+    ("5J6", "Honda"),
+    ("5J8", "Acura"),
+    ("5N1", "Nissan"),
+    ("5NM", "Hyundai"),
+    ("5NP", "Hyundai"),
+    ("5TD", "Toyota"),
+    ("5TF", "Toyota"),
+    ("5UX", "BMW"),
+    ("5XY", "Kia"),
+    ("5YJ", "Tesla"),
+    ("7SA", "Tesla"),
+    ("JA3", "Mitsubishi"),
+    ("JA4", "Mitsubishi"),
+    ("JF1", "Subaru"),
+    ("JF2", "Subaru"),
+    ("JHM", "Honda"),
+    ("JM1", "Mazda"),
+    ("JM3", "Mazda"),
+    ("JN1", "Nissan"),
+    ("JN8", "Nissan"),
+    ("JT2", "Toyota"),
+    ("JTD", "Toyota"),
+    ("JTE", "Toyota"),
+    ("JTH", "Lexus"),
+    ("JTJ", "Lexus"),
+    ("JTM", "Toyota"),
+    ("JTN", "Toyota"),
+    ("KL4", "Buick"),
+    ("KM8", "Hyundai"),
+    ("KMH", "Hyundai"),
+    ("KNA", "Kia"),
+    ("KND", "Kia"),
+    ("SAJ", "Jaguar"),
+    ("SAL", "Land Rover"),
+    ("SCA", "Rolls-Royce"),
+    ("SCF", "Aston Martin"),
+    ("WA1", "Audi"),
+    ("WAU", "Audi"),
+    ("WBA", "BMW"),
+    ("WBS", "BMW"),
+    ("WBY", "BMW"),
+    ("WDC", "Mercedes-Benz"),
+    ("WDD", "Mercedes-Benz"),
+    ("WMW", "MINI"),
+    ("WP0", "Porsche"),
+    ("WP1", "Porsche"),
+    ("WVG", "Volkswagen"),
+    ("WVW", "Volkswagen"),
+    ("YV1", "Volvo"),
+    ("YV4", "Volvo"),
+    ("ZAR", "Alfa Romeo"),
+    ("ZFF", "Ferrari"),
+    ("1F", "Ford"),
+    ("1G", "General Motors"),
+    ("1N", "Nissan"),
+    ("2G", "General Motors"),
+    ("JT", "Toyota"),
+    ("KM", "Hyundai"),
+    ("KN", "Kia"),
+];
+
+/// Fields decoded from a valid VIN.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedVin {
+    pub vin: String,
+    pub wmi: String,
+    pub manufacturer: Option<String>,
+    pub model_year: Option<i32>,
+    pub plant_code: String,
+}
+
+/// Normalizes (trim, uppercase) and validates a VIN, then decodes it.
+/// The error is a human-readable reason suitable for the upload error report.
+pub fn decode_vin(raw: &str) -> Result<DecodedVin, String> {
+    let vin = raw.trim().to_ascii_uppercase();
+
+    let length = vin.chars().count();
+    if length != 17 {
+        return Err(format!("VIN must be 17 characters, got {}", length));
+    }
+    if let Some(c) = vin.chars().find(|c| !c.is_ascii_alphanumeric()) {
+        return Err(format!("VIN contains invalid character '{}'", c));
+    }
+    if let Some(c) = vin.chars().find(|c| matches!(c, 'I' | 'O' | 'Q')) {
+        return Err(format!("VIN contains '{}', which is never used in VINs", c));
+    }
+
+    let bytes = vin.as_bytes();
+    let expected = check_digit(bytes);
+    if bytes[8] != expected {
+        return Err(format!(
+            "VIN check digit is '{}', expected '{}'",
+            bytes[8] as char, expected as char
+        ));
+    }
+
+    let wmi = vin[0..3].to_string();
+    Ok(DecodedVin {
+        manufacturer: lookup_manufacturer(&wmi).map(|s| s.to_string()),
+        model_year: decode_model_year(bytes),
+        plant_code: (bytes[10] as char).to_string(),
+        wmi,
+        vin,
+    })
+}
+
+fn transliterate(c: u8) -> u32 {
+    match c {
+        b'0'..=b'9' => (c - b'0') as u32,
+        b'A' | b'J' => 1,
+        b'B' | b'K' | b'S' => 2,
+        b'C' | b'L' | b'T' => 3,
+        b'D' | b'M' | b'U' => 4,
+        b'E' | b'N' | b'V' => 5,
+        b'F' | b'W' => 6,
+        b'G' | b'P' | b'X' => 7,
+        b'H' | b'Y' => 8,
+        b'R' | b'Z' => 9,
+        _ => 0,
+    }
+}
+
+fn check_digit(vin: &[u8]) -> u8 {
+    let sum: u32 = vin
+        .iter()
+        .zip(WEIGHTS.iter())
+        .map(|(&c, &w)| transliterate(c) * w)
+        .sum();
+    match sum % 11 {
+        10 => b'X',
+        n => b'0' + n as u8,
+    }
+}
+
+fn lookup_manufacturer(wmi: &str) -> Option<&'static str> {
+    WMI_TABLE
+        .iter()
+        .find(|(code, _)| *code == wmi)
+        .or_else(|| WMI_TABLE.iter().find(|(code, _)| code.len() == 2 && wmi.starts_with(code)))
+        .map(|(_, name)| *name)
+}
+
+fn decode_model_year(vin: &[u8]) -> Option<i32> {
+    let index = YEAR_CODES.iter().position(|&c| c == vin[9])? as i32;
+    // For North American light vehicles, an alphabetic 7th character marks the
+    // 2010-2039 cycle and a numeric one the 1980-2009 cycle.
+    if vin[6].is_ascii_alphabetic() {
+        Some(2010 + index)
+    } else {
+        Some(1980 + index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_vin_is_normalized_and_decoded() {
+        let decoded = decode_vin(" 1hgcm82633a004352 ").unwrap();
+        assert_eq!(
+            decoded,
+            DecodedVin {
+                vin: "1HGCM82633A004352".to_string(),
+                wmi: "1HG".to_string(),
+                manufacturer: Some("Honda".to_string()),
+                model_year: Some(2003),
+                plant_code: "A".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn check_digit_is_verified() {
+        let cases = [
+            ("1HGCM82633A004352", Ok(())),
+            ("11111111111111111", Ok(())),
+            ("1HGCM826X3A004302", Ok(())),
+            ("1HGCM82643A004352", Err("VIN check digit is '4', expected '3'")),
+            ("1HGCM82603A004302", Err("VIN check digit is '0', expected 'X'")),
+            ("11111111211111111", Err("VIN check digit is '2', expected '1'")),
+        ];
+        for (vin, expected) in cases {
+            assert_eq!(decode_vin(vin).map(|_| ()), expected.map_err(str::to_string), "{}", vin);
+        }
+    }
+
+    #[test]
+    fn length_is_counted_in_characters() {
+        let cases = [
+            ("1HGCM82633A00435", "VIN must be 17 characters, got 16"),
+            ("1HGCM82633A0043520", "VIN must be 17 characters, got 18"),
+            ("", "VIN must be 17 characters, got 0"),
+            // 16 bytes but 15 characters
+            ("ÄHGCM82633A0043", "VIN must be 17 characters, got 15"),
+            // 18 bytes but 17 characters
+            ("ÄHGCM82633A004352", "VIN contains invalid character 'Ä'"),
+        ];
+        for (vin, reason) in cases {
+            assert_eq!(decode_vin(vin).unwrap_err(), reason, "{}", vin);
+        }
+    }
+
+    #[test]
+    fn rejects_characters_never_used_in_vins() {
+        assert_eq!(decode_vin("1HGCM82633A0O4352").unwrap_err(), "VIN contains 'O', which is never used in VINs");
+        assert_eq!(decode_vin("1HGCM82633A0-4352").unwrap_err(), "VIN contains invalid character '-'");
+    }
+
+    #[test]
+    fn decodes_manufacturer_and_model_year() {
+        let tesla = decode_vin("5YJ3E1EA2KF317000").unwrap();
+        assert_eq!(tesla.manufacturer.as_deref(), Some("Tesla"));
+        // An alphabetic 7th character puts the year code in the 2010-2039 cycle
+        assert_eq!(tesla.model_year, Some(2019));
+
+        // Two-character WMI entries catch the rest of a manufacturer's codes
+        assert_eq!(decode_vin("1GXCM82673A004352").unwrap().manufacturer.as_deref(), Some("General Motors"));
+        assert_eq!(decode_vin("11111111111111111").unwrap().manufacturer, None);
+    }
+}
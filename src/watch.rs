@@ -0,0 +1,270 @@
+use crate::archives::ArchiveFormat;
+use crate::reader::FileFormat;
+use crate::storage::{dealer_upload_dir, display_file_name, generated_file_name, max_upload_bytes};
+use crate::uploads::{create_upload, CreateUploadError, NewUpload};
+use chrono::Utc;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+// Subdirectories of a watched directory. A file is claimed by moving it to
+// `processing` as `claimed-<timestamp>-<name>` before its upload is created, then
+// waits there as `<upload_id>-<name>` until the upload finishes.
+const PROCESSING_DIR: &str = "processing";
+const CLAIMED_PREFIX: &str = "claimed-";
+const PROCESSED_DIR: &str = "processed";
+const FAILED_DIR: &str = "failed";
+const DEFAULT_WATCH_INTERVAL_SECS: u64 = 30;
+
+/// A directory whose files are imported for one dealer.
+#[derive(Debug, Clone)]
+pub struct WatchDir {
+    pub dealer_id: i32,
+    pub path: PathBuf,
+}
+
+/// Parses `WATCH_DIRS`, a comma-separated list of `<dealer_id>=<directory>` pairs.
+pub fn watch_dirs_from_env() -> anyhow::Result<Vec<WatchDir>> {
+    let value = std::env::var("WATCH_DIRS").unwrap_or_default();
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (dealer_id, path) = entry
+                .split_once('=')
+                .ok_or_else(|| anyhow::anyhow!("WATCH_DIRS entry '{}' is not <dealer_id>=<directory>", entry))?;
+            let dealer_id = dealer_id
+                .trim()
+                .parse()
+                .map_err(|_| anyhow::anyhow!("WATCH_DIRS entry '{}' has an invalid dealer id", entry))?;
+            Ok(WatchDir {
+                dealer_id,
+                path: PathBuf::from(path.trim()),
+            })
+        })
+        .collect()
+}
+
+// Seconds between scans of each watched directory (`WATCH_INTERVAL_SECS`, default 30)
+fn watch_interval() -> Duration {
+    let secs = std::env::var("WATCH_INTERVAL_SECS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .filter(|secs| *secs > 0)
+        .unwrap_or(DEFAULT_WATCH_INTERVAL_SECS);
+    Duration::from_secs(secs)
+}
+
+/// Starts a watcher for each directory in `WATCH_DIRS`. Each one imports the files
+/// dropped into its directory as uploads of its dealer.
+pub async fn start_watchers(pool: PgPool) -> anyhow::Result<()> {
+    for dir in watch_dirs_from_env()? {
+        let exists: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM dealers WHERE id = $1)")
+            .bind(dir.dealer_id)
+            .fetch_one(&pool)
+            .await?;
+        if !exists {
+            eprintln!("Not watching {}: dealer {} does not exist", dir.path.display(), dir.dealer_id);
+            continue;
+        }
+        for sub in [PROCESSING_DIR, PROCESSED_DIR, FAILED_DIR] {
+            tokio::fs::create_dir_all(dir.path.join(sub)).await?;
+        }
+
+        println!("Watching {} for dealer {}", dir.path.display(), dir.dealer_id);
+        let pool = pool.clone();
+        tokio::spawn(async move { run_watcher(pool, dir).await });
+    }
+    Ok(())
+}
+
+async fn run_watcher(pool: PgPool, dir: WatchDir) {
+    let interval = watch_interval();
+    let mut pending = HashMap::new();
+    loop {
+        if let Err(e) = finish_processed_files(&pool, &dir).await {
+            eprintln!("Watcher for {} failed to file finished uploads: {}", dir.path.display(), e);
+        }
+        if let Err(e) = scan_new_files(&pool, &dir, &mut pending).await {
+            eprintln!("Watcher for {} failed to scan for new files: {}", dir.path.display(), e);
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
+// A file is imported once its size and modification time are unchanged since the
+// previous scan, so files still being written aren't read half-way through.
+async fn scan_new_files(
+    pool: &PgPool,
+    dir: &WatchDir,
+    pending: &mut HashMap<PathBuf, (u64, SystemTime)>,
+) -> anyhow::Result<()> {
+    let mut seen = HashMap::new();
+    let mut entries = tokio::fs::read_dir(&dir.path).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let metadata = entry.metadata().await?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !metadata.is_file() || name.starts_with('.') {
+            continue;
+        }
+        seen.insert(entry.path(), (metadata.len(), metadata.modified()?));
+    }
+
+    for (path, state) in &seen {
+        if pending.get(path) == Some(state) {
+            if let Err(e) = import_file(pool, dir, path, state.0).await {
+                eprintln!("Watcher failed to import {}: {}", path.display(), e);
+            }
+        }
+    }
+    *pending = seen;
+    Ok(())
+}
+
+// Claims a file and queues it like a form upload. The file is moved out of the
+// directory before its upload is created, so it is never imported twice, and the
+// upload records the claimed path, so a claimed file is filed by its upload or
+// returned to be tried again on the next scan.
+async fn import_file(pool: &PgPool, dir: &WatchDir, path: &Path, len: u64) -> anyhow::Result<()> {
+    let filename = display_file_name(&path.file_name().unwrap_or_default().to_string_lossy());
+    if FileFormat::from_path(&filename).is_none() && ArchiveFormat::from_path(&filename).is_none() {
+        return move_to_failed(dir, path, &timestamped(&filename), "Not a CSV, Excel or archive file").await;
+    }
+    let max_bytes = max_upload_bytes();
+    if len > max_bytes {
+        let reason = format!("File is larger than MAX_UPLOAD_BYTES ({} bytes)", max_bytes);
+        return move_to_failed(dir, path, &timestamped(&filename), &reason).await;
+    }
+
+    let claimed = dir
+        .path
+        .join(PROCESSING_DIR)
+        .join(format!("{}{}", CLAIMED_PREFIX, timestamped(&filename)));
+    tokio::fs::rename(path, &claimed).await?;
+
+    match create_watch_upload(pool, dir, &claimed, &filename).await {
+        Ok(upload_id) => file_claimed(dir, &claimed, upload_id, &filename).await,
+        Err(CreateUploadError::Rejected(reason)) => {
+            move_to_failed(dir, &claimed, &timestamped(&filename), &reason).await
+        }
+        Err(CreateUploadError::Failed(e)) => {
+            // Nothing was recorded, so the file goes back to be tried again
+            tokio::fs::rename(&claimed, path).await?;
+            Err(e)
+        }
+    }
+}
+
+// Copies a claimed file into the dealer's upload directory and creates its upload.
+async fn create_watch_upload(
+    pool: &PgPool,
+    dir: &WatchDir,
+    claimed: &Path,
+    filename: &str,
+) -> Result<i32, CreateUploadError> {
+    let dest_dir = dealer_upload_dir(dir.dealer_id);
+    let file_path = dest_dir.join(generated_file_name(filename));
+    let copied = async {
+        tokio::fs::create_dir_all(&dest_dir).await?;
+        tokio::fs::copy(claimed, &file_path).await
+    };
+    copied.await.map_err(|e| CreateUploadError::Failed(e.into()))?;
+
+    let new_upload = NewUpload {
+        dealer_id: dir.dealer_id,
+        filename: filename.to_string(),
+        file_path,
+        all_or_nothing: false,
+        sheet_name: None,
+        adapter: None,
+        source: "watch",
+        watch_path: Some(claimed.to_string_lossy().to_string()),
+    };
+    create_upload(pool, new_upload).await
+}
+
+// Renames a claimed file after the upload made from it.
+async fn file_claimed(dir: &WatchDir, claimed: &Path, upload_id: i32, filename: &str) -> anyhow::Result<()> {
+    let processing = dir.path.join(PROCESSING_DIR).join(format!("{}-{}", upload_id, filename));
+    tokio::fs::rename(claimed, processing).await?;
+    Ok(())
+}
+
+// Files a claim left behind by a watcher that stopped part way through: under its
+// upload if one was created from it, else back in the directory to be imported.
+async fn recover_claimed_file(pool: &PgPool, dir: &WatchDir, claimed: &Path, name: &str) -> anyhow::Result<()> {
+    let Some(filename) = name
+        .strip_prefix(CLAIMED_PREFIX)
+        .and_then(|rest| rest.split_once('-'))
+        .map(|(_, filename)| filename)
+    else {
+        return Ok(());
+    };
+    let upload_id: Option<i32> = sqlx::query_scalar("SELECT id FROM uploads WHERE dealer_id = $1 AND watch_path = $2")
+        .bind(dir.dealer_id)
+        .bind(claimed.to_string_lossy().to_string())
+        .fetch_optional(pool)
+        .await?;
+    match upload_id {
+        Some(upload_id) => file_claimed(dir, claimed, upload_id, filename).await,
+        None => {
+            // A newer file of the same name waiting to be imported goes first
+            let returned = dir.path.join(filename);
+            if !tokio::fs::try_exists(&returned).await? {
+                tokio::fs::rename(claimed, returned).await?;
+            }
+            Ok(())
+        }
+    }
+}
+
+// Moves files whose upload has finished from `processing` to `processed`, or to
+// `failed` with the upload's error. An archive whose files only partly failed counts
+// as processed.
+async fn finish_processed_files(pool: &PgPool, dir: &WatchDir) -> anyhow::Result<()> {
+    let mut entries = tokio::fs::read_dir(dir.path.join(PROCESSING_DIR)).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with(CLAIMED_PREFIX) {
+            recover_claimed_file(pool, dir, &entry.path(), &name).await?;
+            continue;
+        }
+        let Some(upload_id) = name.split_once('-').and_then(|(id, _)| id.parse::<i32>().ok()) else {
+            continue;
+        };
+
+        let upload: Option<(String, Option<String>)> =
+            sqlx::query_as("SELECT status, error_message FROM uploads WHERE id = $1 AND dealer_id = $2")
+                .bind(upload_id)
+                .bind(dir.dealer_id)
+                .fetch_optional(pool)
+                .await?;
+        match upload {
+            Some((status, _)) if status == "processing" => {}
+            Some((status, error)) if status == "error" => {
+                let reason = error.unwrap_or_else(|| "Upload failed".to_string());
+                move_to_failed(dir, &entry.path(), &name, &reason).await?;
+            }
+            Some(_) => {
+                tokio::fs::rename(entry.path(), dir.path.join(PROCESSED_DIR).join(&name)).await?;
+            }
+            None => move_to_failed(dir, &entry.path(), &name, "The upload was deleted before it finished").await?,
+        }
+    }
+    Ok(())
+}
+
+// Moves a file to `failed` with a `<name>.error.txt` beside it giving the reason.
+async fn move_to_failed(dir: &WatchDir, path: &Path, name: &str, reason: &str) -> anyhow::Result<()> {
+    let failed = dir.path.join(FAILED_DIR);
+    tokio::fs::rename(path, failed.join(name)).await?;
+    tokio::fs::write(failed.join(format!("{}.error.txt", name)), format!("{}\n", reason)).await?;
+    Ok(())
+}
+
+// Files that never became an upload have no id to keep nightly exports of the same name apart.
+fn timestamped(filename: &str) -> String {
+    format!("{}-{}", Utc::now().format("%Y%m%dT%H%M%S"), filename)
+}